@@ -0,0 +1,24 @@
+//! Time building the escape sequences of a style, `cargo bench --bench sequence`.
+
+use std::hint::black_box;
+use std::time::Instant;
+
+use mark_rs::style::{AnsiSequence, Color, Style};
+
+const ITERATIONS: u32 = 100_000;
+
+fn main() {
+    let styles = [
+        ("attribute", Style::builder().bold()),
+        ("colors", Style::builder().fg(Color::RED).bg(Color::RGB { r: 12, g: 34, b: 56 }).italic()),
+        ("link", Style::builder().fg(Color::BLUE).underline().link("https://example.com")),
+    ];
+    for (name, style) in styles {
+        let start = Instant::now();
+        for _ in 0..ITERATIONS {
+            black_box(black_box(&style).sequence());
+            black_box(black_box(&style).reset_sequence());
+        }
+        println!("{:<10} {:?} per sequence and reset", name, start.elapsed() / ITERATIONS);
+    }
+}
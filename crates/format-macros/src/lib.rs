@@ -0,0 +1,141 @@
+use proc_macro::{Delimiter, Group, Ident, Literal, Punct, Spacing, TokenStream, TokenTree};
+
+/// Implementation detail of `format::format!`.
+///
+/// Takes the format literal, the already built argument list, and the names of the explicit named
+/// arguments: `"{name}", [...], [a, b]`. Every `{ident}` placeholder that isn't an explicit named
+/// argument is captured from the scope as `ident = ident`, the same as `std::format!`. Returns the
+/// argument list with the captured arguments appended.
+#[proc_macro]
+pub fn __capture(input: TokenStream) -> TokenStream {
+    let mut tokens = input.into_iter();
+
+    let literal = match literal(tokens.next()) {
+        Some(literal) => literal,
+        None => panic!("expected a format string literal"),
+    };
+    tokens.next();
+
+    let args = match tokens.next() {
+        Some(TokenTree::Group(group)) if group.delimiter() == Delimiter::Bracket => group.stream(),
+        _ => panic!("expected a list of arguments"),
+    };
+    tokens.next();
+
+    let names = match tokens.next() {
+        Some(TokenTree::Group(group)) if group.delimiter() == Delimiter::Bracket => group
+            .stream()
+            .into_iter()
+            .filter_map(|t| match t {
+                TokenTree::Ident(ident) => Some(ident.to_string()),
+                _ => None,
+            })
+            .collect::<Vec<_>>(),
+        _ => Vec::new(),
+    };
+
+    let mut output = args;
+    let mut captured = Vec::new();
+    for name in placeholders(&literal.to_string()) {
+        if names.contains(&name) || captured.contains(&name) {
+            continue;
+        }
+
+        // The identifier uses the span of the literal so it resolves in the caller's scope
+        let ident = Ident::new(&name, literal.span());
+        let pair = Group::new(
+            Delimiter::Parenthesis,
+            TokenStream::from_iter([
+                TokenTree::Literal(Literal::string(&name)),
+                TokenTree::Punct(Punct::new(',', Spacing::Alone)),
+                TokenTree::Ident(ident),
+            ]),
+        );
+        output.extend([
+            TokenTree::Group(pair),
+            TokenTree::Punct(Punct::new('.', Spacing::Alone)),
+            TokenTree::Ident(Ident::new("into_argument", literal.span())),
+            TokenTree::Group(Group::new(Delimiter::Parenthesis, TokenStream::new())),
+            TokenTree::Punct(Punct::new(',', Spacing::Alone)),
+        ]);
+        captured.push(name);
+    }
+
+    TokenStream::from(TokenTree::Group(Group::new(Delimiter::Bracket, output)))
+}
+
+/// Unwrap a literal which is wrapped in an invisible group when passed through `macro_rules`.
+fn literal(token: Option<TokenTree>) -> Option<Literal> {
+    match token? {
+        TokenTree::Literal(literal) => Some(literal),
+        TokenTree::Group(group) if group.delimiter() == Delimiter::None => {
+            let mut inner = group.stream().into_iter();
+            match (literal(inner.next()), inner.next()) {
+                (Some(literal), None) => Some(literal),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Names of the bare identifier placeholders, `{name}` or `{name:...}`, and the named widths and
+/// precisions, `{:name$}`, in the source of a string literal.
+fn placeholders(source: &str) -> Vec<String> {
+    let mut names = Vec::new();
+    let mut chars = source.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            // Skip escapes so `\u{..}` isn't treated as a placeholder
+            '\\' if chars.next() == Some('u') && chars.peek() == Some(&'{') => {
+                for c in chars.by_ref() {
+                    if c == '}' {
+                        break;
+                    }
+                }
+            }
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+            }
+            '{' => {
+                let mut name = String::new();
+                while let Some(c) = chars.next_if(|c| *c != '}' && *c != ':') {
+                    name.push(c);
+                }
+                let name = name.trim();
+                if is_ident(name) {
+                    names.push(name.to_string());
+                }
+
+                // Widths and precisions can also refer to arguments by name, `{:width$}`
+                if chars.next_if_eq(&':').is_some() {
+                    let mut spec = String::new();
+                    while let Some(c) = chars.next_if(|c| *c != '}') {
+                        spec.push(c);
+                    }
+                    // Skip the fill and alignment, `{:>width$}` or `{:*>width$}`
+                    let spec = match spec.char_indices().nth(1) {
+                        Some((i, '<' | '^' | '>')) => &spec[i + 1..],
+                        _ => spec.strip_prefix(['<', '^', '>']).unwrap_or(&spec),
+                    };
+                    for count in spec.split('.') {
+                        // Ignore the type after the count, `{:width$e}`
+                        let count = count.strip_suffix(['e', 'E']).filter(|c| c.ends_with('$')).unwrap_or(count);
+                        if let Some(name) = count.strip_suffix('$').filter(|name| is_ident(name)) {
+                            names.push(name.to_string());
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    names
+}
+
+fn is_ident(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|c| c == '_' || c.is_alphabetic())
+        && chars.all(|c| c == '_' || c.is_alphanumeric())
+        && name != "_"
+}
@@ -3,6 +3,5 @@ extern crate format;
 use format::format;
 
 fn main() {
-    println!("Hello, world!");
-    format!("Hello, {}!", "world", world="Hello");
+    println!("{}", format!("Hello, {}!", "world", world="Hello"));
 }
\ No newline at end of file
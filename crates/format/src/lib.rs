@@ -1,8 +1,80 @@
-use std::fmt::Display;
+//! Runtime string templates in the style of `std::format!`.
+//!
+//! [`formatter`] renders a template with a list of [`Arg`]s, `{}` takes the next positional
+//! argument, `{0}` a positional argument by index, and `{name}` a named argument. `{{` and `}}`
+//! escape the braces. Malformed templates, like an unmatched brace or a placeholder without an
+//! argument, are a [`FormatError`] from [`try_formatter`].
+//!
+//! After a colon, a placeholder takes a spec like `std`'s, `[[fill]align][width][.precision][type]`,
+//! Ex: `{:*^9}` centers the argument in 9 columns padded with `*`. Widths are measured in display
+//! columns, so wide characters and ansi escape sequences are padded by how they show in a terminal.
+//!
+//! An argument that is a style, see [`Argument::style`], wraps text between `{name:style}` and
+//! `{name:/style}`, or `{:/style}` to close the innermost style. Closing a nested style applies
+//! the outer styles again instead of resetting everything,
+//! Ex: `format!("{a:style}x{b:style}y{:/style}z{:/style}", a = bold, b = red)`.
+//! Like any other placeholder, `{:style}` takes the next positional argument, so a named style is
+//! opened with its name, `format!("{s:style}text{:/style}", s = style)`.
+//!
+//! The [`format!`] and [`try_format!`] macros build the arguments, capturing bare identifiers
+//! from the surrounding scope.
 
-pub trait Argument {}
+use std::fmt::{Display, Formatter};
 
-impl<const N: usize> Argument for [Arg; N] {}
+use unicode_width::UnicodeWidthChar;
+
+/// How a placeholder renders its argument, selected by the type at the end of the spec.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FormatKind {
+    /// `{}`
+    Display,
+    /// `{:e}`
+    LowerExp,
+    /// `{:E}`
+    UpperExp,
+}
+
+/// Alignment of an argument padded to a width.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Align {
+    Left,
+    Center,
+    Right,
+}
+
+/// A value that can be substituted into a format placeholder.
+pub trait Argument {
+    fn render(&self) -> String;
+
+    /// Render the argument as the kind of format with an optional precision. `None` if the
+    /// argument doesn't support the kind of format.
+    ///
+    /// Only `Display` is supported by default, where the precision is the maximum number of
+    /// characters like `std` does for strings.
+    fn render_as(&self, kind: FormatKind, precision: Option<usize>) -> Option<String> {
+        match (kind, precision) {
+            (FormatKind::Display, None) => Some(self.render()),
+            (FormatKind::Display, Some(precision)) => Some(self.render().chars().take(precision).collect()),
+            _ => None,
+        }
+    }
+
+    /// The argument as a width or precision, `{:w$}`. `None` if it isn't a non-negative integer.
+    fn as_count(&self) -> Option<usize> {
+        None
+    }
+
+    /// Alignment used when the argument is padded to a width. Defaults to the left.
+    fn default_align(&self) -> Align {
+        Align::Left
+    }
+
+    /// The escape sequences that open and close the argument as a style, `{name:style}` and
+    /// `{name:/style}`. `None` if the argument isn't a style.
+    fn style(&self) -> Option<(String, String)> {
+        None
+    }
+}
 
 pub enum Arg {
     Positional(Box<dyn Argument>),
@@ -24,44 +96,482 @@ impl<S: ToString, A: Argument + 'static> IntoArgument for (S, A) {
     }
 }
 
-impl Argument for &str {}
-impl<A: Argument> Argument for Option<A> {}
-impl Argument for () {}
+macro_rules! display_argument {
+    ($($ty: ty),*) => {
+        $(
+            impl Argument for $ty {
+                fn render(&self) -> String {
+                    self.to_string()
+                }
+            }
+        )*
+    };
+}
+
+display_argument!(&str, String, char, bool);
+
+impl<A: Argument> Argument for Option<A> {
+    fn render(&self) -> String {
+        self.as_ref().map(|a| a.render()).unwrap_or_default()
+    }
 
-pub fn formatter<S: Display, A: IntoArgument>(fmt: &str, args: A) {
-    println!("Hello, world!");
+    fn render_as(&self, kind: FormatKind, precision: Option<usize>) -> Option<String> {
+        match self {
+            Some(a) => a.render_as(kind, precision),
+            None => Some(String::new()),
+        }
+    }
+}
+impl Argument for () {
+    fn render(&self) -> String {
+        String::new()
+    }
 }
 
+macro_rules! numeric_argument {
+    ($count: ident => $($ty: ty),*) => {
+        $(
+            impl Argument for $ty {
+                fn render(&self) -> String {
+                    self.to_string()
+                }
+
+                fn render_as(&self, kind: FormatKind, precision: Option<usize>) -> Option<String> {
+                    Some(match (kind, precision) {
+                        (FormatKind::Display, None) => self.to_string(),
+                        (FormatKind::Display, Some(p)) => std::format!("{:.*}", p, self),
+                        (FormatKind::LowerExp, None) => std::format!("{:e}", self),
+                        (FormatKind::LowerExp, Some(p)) => std::format!("{:.*e}", p, self),
+                        (FormatKind::UpperExp, None) => std::format!("{:E}", self),
+                        (FormatKind::UpperExp, Some(p)) => std::format!("{:.*E}", p, self),
+                    })
+                }
+
+                fn as_count(&self) -> Option<usize> {
+                    $count(*self)
+                }
+
+                fn default_align(&self) -> Align {
+                    Align::Right
+                }
+            }
+        )*
+    };
+}
+
+fn float_count<T>(_: T) -> Option<usize> {
+    None
+}
+
+fn integer_count<T: TryInto<usize>>(value: T) -> Option<usize> {
+    value.try_into().ok()
+}
+
+numeric_argument!(float_count => f32, f64);
+numeric_argument!(integer_count => i8, i16, i32, i64, i128, isize, u8, u16, u32, u64, u128, usize);
+
+/// Error from a malformed format template.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FormatError {
+    /// A `{` without a closing `}`, or a `}` without an opening `{`, at the byte position
+    UnmatchedBrace { position: usize },
+    /// A `{name}` placeholder without a matching named argument
+    UnknownNamedArgument(String),
+    /// A positional placeholder that refers past the number of positional arguments
+    IndexOutOfRange { index: usize, count: usize },
+    /// A placeholder's format, like `{:e}`, that its argument doesn't support
+    UnsupportedFormat { spec: String },
+    /// A placeholder's format that can't be parsed
+    InvalidSpec { spec: String },
+    /// A width or precision, `{:w$}`, that refers to an argument that isn't a non-negative integer
+    InvalidCount(String),
+    /// A `{:/style}` without an open style, or that closes a different argument than the
+    /// innermost open style, or a `{:style}` that is never closed
+    UnbalancedStyle,
+}
+
+impl Display for FormatError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FormatError::UnmatchedBrace { position } => write!(f, "unmatched brace at position {}", position),
+            FormatError::UnknownNamedArgument(name) => write!(f, "there is no argument named `{}`", name),
+            FormatError::IndexOutOfRange { index, count } => write!(
+                f,
+                "invalid reference to positional argument {} ({} {} given)",
+                index,
+                count,
+                if *count == 1 { "argument was" } else { "arguments were" }
+            ),
+            FormatError::UnsupportedFormat { spec } => write!(f, "the argument doesn't support the format `{{:{}}}`", spec),
+            FormatError::InvalidSpec { spec } => write!(f, "invalid format `{{:{}}}`", spec),
+            FormatError::InvalidCount(argument) => {
+                write!(f, "width or precision argument `{}` isn't a non-negative integer", argument)
+            }
+            FormatError::UnbalancedStyle => write!(f, "every `{{:style}}` must be closed by a matching `{{:/style}}`"),
+        }
+    }
+}
+
+impl std::error::Error for FormatError {}
+
+/// Which argument a placeholder refers to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Reference<'a> {
+    Index(usize),
+    Name(&'a str),
+}
+
+/// Width or precision of a placeholder.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Count<'a> {
+    /// `{:5}`
+    Literal(usize),
+    /// `{:1$}` or `{:name$}`
+    Argument(Reference<'a>),
+}
+
+/// Parsed format of a placeholder: `[[fill]align][width][.precision][type]`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Spec<'a> {
+    source: &'a str,
+    /// Character the argument is padded with, Ex: `{:*^9}`. Defaults to a space
+    fill: char,
+    /// `<`, `^`, or `>`. The argument's default alignment is used when there is none
+    align: Option<Align>,
+    width: Option<Count<'a>>,
+    precision: Option<Count<'a>>,
+    kind: FormatKind,
+}
+
+impl<'a> Spec<'a> {
+    fn parse(source: &'a str) -> Result<Spec<'a>, FormatError> {
+        let invalid = || FormatError::InvalidSpec { spec: source.to_string() };
+        let (rest, kind) = match source.strip_suffix('e') {
+            Some(rest) => (rest, FormatKind::LowerExp),
+            None => match source.strip_suffix('E') {
+                Some(rest) => (rest, FormatKind::UpperExp),
+                None => (source, FormatKind::Display),
+            },
+        };
+        let align = |c: char| match c {
+            '<' => Some(Align::Left),
+            '^' => Some(Align::Center),
+            '>' => Some(Align::Right),
+            _ => None,
+        };
+        // The fill is only a fill if an alignment follows it
+        let mut chars = rest.chars();
+        let (rest, fill, align) = match (chars.next(), chars.next().and_then(align)) {
+            (Some(fill), Some(a)) => (&rest[fill.len_utf8() + 1..], fill, Some(a)),
+            (Some(c), _) => match align(c) {
+                Some(a) => (&rest[1..], ' ', Some(a)),
+                None => (rest, ' ', None),
+            },
+            (None, _) => (rest, ' ', None),
+        };
+        let (width, precision) = match rest.split_once('.') {
+            Some((width, precision)) => (width, Some(precision)),
+            None => (rest, None),
+        };
+
+        let count = |value: &'a str| -> Result<Option<Count<'a>>, FormatError> {
+            if value.is_empty() {
+                return Ok(None);
+            }
+            match value.strip_suffix('$') {
+                Some(reference) => {
+                    if let Ok(index) = reference.parse::<usize>() {
+                        Ok(Some(Count::Argument(Reference::Index(index))))
+                    } else if is_ident(reference) {
+                        Ok(Some(Count::Argument(Reference::Name(reference))))
+                    } else {
+                        Err(invalid())
+                    }
+                }
+                None => value.parse::<usize>().map(|c| Some(Count::Literal(c))).map_err(|_| invalid()),
+            }
+        };
+
+        let precision = match precision {
+            Some(precision) => Some(count(precision)?.ok_or_else(invalid)?),
+            None => None,
+        };
+        Ok(Spec { source, fill, align, width: count(width)?, precision, kind })
+    }
+}
+
+fn is_ident(value: &str) -> bool {
+    let mut chars = value.chars();
+    chars.next().is_some_and(|c| c == '_' || c.is_alphabetic())
+        && chars.all(|c| c == '_' || c.is_alphanumeric())
+        && value != "_"
+}
+
+/// A piece of a parsed format template.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Piece<'a> {
+    Literal(&'a str),
+    Placeholder { reference: Reference<'a>, spec: Spec<'a> },
+    /// `{name:style}`
+    StyleOpen(Reference<'a>),
+    /// `{name:/style}` or `{:/style}` which closes the innermost open style
+    StyleClose(Option<Reference<'a>>),
+}
+
+/// Split a template into literal text and placeholders. `{{` and `}}` are escaped braces.
+fn parse(fmt: &str) -> Result<Vec<Piece<'_>>, FormatError> {
+    let mut pieces = Vec::new();
+    let mut next_index = 0;
+    let mut start = 0;
+    let mut chars = fmt.char_indices().peekable();
+
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '{' | '}' if chars.peek().map(|(_, n)| *n) == Some(c) => {
+                pieces.push(Piece::Literal(&fmt[start..i + 1]));
+                chars.next();
+                start = i + 2;
+            }
+            '}' => return Err(FormatError::UnmatchedBrace { position: i }),
+            '{' => {
+                pieces.push(Piece::Literal(&fmt[start..i]));
+                let end = fmt[i..]
+                    .find('}')
+                    .map(|e| e + i)
+                    .ok_or(FormatError::UnmatchedBrace { position: i })?;
+                let inner = &fmt[i + 1..end];
+                if inner.contains('{') {
+                    return Err(FormatError::UnmatchedBrace { position: i });
+                }
+
+                let (name, spec) = inner.split_once(':').unwrap_or((inner, ""));
+                let name = name.trim();
+                // A close without a reference doesn't take the next positional argument
+                if name.is_empty() && spec == "/style" {
+                    pieces.push(Piece::StyleClose(None));
+                    while chars.next_if(|(n, _)| *n <= end).is_some() {}
+                    start = end + 1;
+                    continue;
+                }
+                let reference = if name.is_empty() {
+                    next_index += 1;
+                    Reference::Index(next_index - 1)
+                } else if let Ok(index) = name.parse::<usize>() {
+                    Reference::Index(index)
+                } else {
+                    Reference::Name(name)
+                };
+                pieces.push(match spec {
+                    "style" => Piece::StyleOpen(reference),
+                    "/style" => Piece::StyleClose(Some(reference)),
+                    _ => Piece::Placeholder { reference, spec: Spec::parse(spec)? },
+                });
+
+                while chars.next_if(|(n, _)| *n <= end).is_some() {}
+                start = end + 1;
+            }
+            _ => {}
+        }
+    }
+    pieces.push(Piece::Literal(&fmt[start..]));
+    pieces.retain(|p| *p != Piece::Literal(""));
+    Ok(pieces)
+}
+
+/// Find the argument a reference refers to.
+fn lookup<'a>(args: &'a [Arg], positional: &[&'a dyn Argument], reference: &Reference) -> Result<&'a dyn Argument, FormatError> {
+    match reference {
+        Reference::Index(index) => positional
+            .get(*index)
+            .copied()
+            .ok_or(FormatError::IndexOutOfRange { index: *index, count: positional.len() }),
+        Reference::Name(name) => args
+            .iter()
+            .find_map(|a| match a {
+                Arg::Named(n, value) if n == name => Some(value.as_ref()),
+                _ => None,
+            })
+            .ok_or_else(|| FormatError::UnknownNamedArgument(name.to_string())),
+    }
+}
+
+/// Number of columns shown in a terminal, skipping ansi escape sequences so styled text,
+/// Ex: `"\x1b[1;31mx\x1b[0m"`, is as wide as its plain text.
+pub fn visible_width(text: &str) -> usize {
+    let mut width = 0;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '\x1b' {
+            width += c.width().unwrap_or(0);
+            continue;
+        }
+        match chars.next() {
+            // Control sequence ending with a final byte in `@..=~`
+            Some('[') => {
+                for c in chars.by_ref() {
+                    if ('@'..='~').contains(&c) {
+                        break;
+                    }
+                }
+            }
+            // Operating system command ending with BEL or ST, `\x1b\\`
+            Some(']') => {
+                while let Some(c) = chars.next() {
+                    if c == '\x07' || (c == '\x1b' && chars.next_if_eq(&'\\').is_some()) {
+                        break;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    width
+}
+
+/// Pad the text with the fill to the width by its [`visible_width`]. Text that is already wider
+/// than the width isn't truncated.
+fn pad(text: String, width: usize, fill: char, align: Align) -> String {
+    let padding = width.saturating_sub(visible_width(&text));
+    let (before, after) = match align {
+        Align::Left => (0, padding),
+        Align::Center => (padding / 2, padding - padding / 2),
+        Align::Right => (padding, 0),
+    };
+    let fill = |count: usize| std::iter::repeat_n(fill, count).collect::<String>();
+    std::format!("{}{}{}", fill(before), text, fill(after))
+}
+
+/// Render the template with the arguments, returning an error for malformed templates.
+pub fn try_formatter(fmt: &str, args: &[Arg]) -> Result<String, FormatError> {
+    let positional = args
+        .iter()
+        .filter_map(|a| match a {
+            Arg::Positional(value) => Some(value.as_ref()),
+            Arg::Named(_, _) => None,
+        })
+        .collect::<Vec<_>>();
+    let count = |count: &Option<Count>| -> Result<Option<usize>, FormatError> {
+        match count {
+            None => Ok(None),
+            Some(Count::Literal(count)) => Ok(Some(*count)),
+            Some(Count::Argument(reference)) => lookup(args, &positional, reference)?
+                .as_count()
+                .map(Some)
+                .ok_or_else(|| FormatError::InvalidCount(match reference {
+                    Reference::Index(index) => index.to_string(),
+                    Reference::Name(name) => name.to_string(),
+                })),
+        }
+    };
+
+    let mut output = String::new();
+    // The open styles, innermost last, with the reference that opened them and their sequences
+    let mut styles: Vec<(Reference, String, String)> = Vec::new();
+    for piece in parse(fmt)? {
+        match piece {
+            Piece::Literal(text) => output.push_str(text),
+            Piece::StyleOpen(reference) => {
+                let (open, close) = lookup(args, &positional, &reference)?
+                    .style()
+                    .ok_or_else(|| FormatError::UnsupportedFormat { spec: "style".to_string() })?;
+                output.push_str(&open);
+                styles.push((reference, open, close));
+            }
+            Piece::StyleClose(reference) => {
+                let (_, _, close) = match (styles.last(), reference) {
+                    (Some((open, _, _)), Some(reference)) if *open != reference => return Err(FormatError::UnbalancedStyle),
+                    (Some(_), _) => styles.pop().unwrap(),
+                    (None, _) => return Err(FormatError::UnbalancedStyle),
+                };
+                // Closing a style can reset attributes the outer styles also set, so the styles
+                // that are still open are applied again
+                output.push_str(&close);
+                for (_, open, _) in &styles {
+                    output.push_str(open);
+                }
+            }
+            Piece::Placeholder { reference, spec } => {
+                let value = lookup(args, &positional, &reference)?;
+                let rendered = value
+                    .render_as(spec.kind, count(&spec.precision)?)
+                    .ok_or_else(|| FormatError::UnsupportedFormat { spec: spec.source.to_string() })?;
+                match count(&spec.width)? {
+                    Some(width) => output.push_str(&pad(rendered, width, spec.fill, spec.align.unwrap_or(value.default_align()))),
+                    None => output.push_str(&rendered),
+                }
+            }
+        }
+    }
+    if !styles.is_empty() {
+        return Err(FormatError::UnbalancedStyle);
+    }
+    Ok(output)
+}
+
+/// Render the template with the arguments.
+///
+/// # Panics
+/// If the template is malformed. Use [`try_formatter`] to handle the error instead.
+pub fn formatter(fmt: &str, args: &[Arg]) -> String {
+    match try_formatter(fmt, args) {
+        Ok(output) => output,
+        Err(error) => panic!("invalid format template: {}", error),
+    }
+}
+
+#[doc(hidden)]
+pub use format_macros::__capture;
+
+/// Format a string with positional and named arguments.
+///
+/// Like `std::format!`, bare identifier placeholders, `{name}`, without a matching named argument
+/// are captured from the surrounding scope.
+///
+/// Widths and precisions can come from arguments, together in one placeholder as well,
+/// Ex: `format!("{:w$.p$}", 3.14159, w = 8, p = 2)` == `"    3.14"`.
+///
+/// # Panics
+/// If the template is malformed or a width or precision argument isn't a non-negative integer,
+/// use [`try_format!`] to get a [`FormatError`] instead.
 #[macro_export]
 macro_rules! format {
     ($fmt: literal) => {
-        $crate::formatter($fmt, ());
+        $crate::format!(@ formatter, $fmt, [], [],)
     };
     ($fmt: literal, $($args: tt)*) => {
-        $crate::format!(@ $fmt, [], $($args)*)
+        $crate::format!(@ formatter, $fmt, [], [], $($args)*)
     };
-    (@ $fmt: literal, [$($args: tt)*], $key: ident = $value: expr, $($arg: tt)*) => {
-        $crate::format!(@ $fmt, [$($args)* ($key, $value).into_argument(),], $($args)*)
+    // Every argument is appended with its own trailing comma, so the accumulated list is always
+    // a valid array body, `[a, b,]`, and never starts with a comma
+    (@ $f: ident, $fmt: literal, [$($args: tt)*], [$($names: ident)*], $key: ident = $value: expr, $($arg: tt)*) => {
+        $crate::format!(@ $f, $fmt, [$($args)* (stringify!($key), $value).into_argument(),], [$($names)* $key], $($arg)*)
     };
-    (@ $fmt: literal, [$($args: tt)*], $value: expr, $($arg: tt)*) => {
-        $crate::format!(@ $fmt, [$($args)* ($value).into_argument(),], $($args)*)
+    (@ $f: ident, $fmt: literal, [$($args: tt)*], [$($names: ident)*], $value: expr, $($arg: tt)*) => {
+        $crate::format!(@ $f, $fmt, [$($args)* ($value).into_argument(),], [$($names)*], $($arg)*)
     };
-    (@ $fmt: literal, [$($args: tt)*], $key: ident = $value: expr) => {
-        {
-            use $crate::IntoArgument;
-            $crate::formatter($fmt, ($($args)*, ($key, $value).into_argument(),))
-        }
+    (@ $f: ident, $fmt: literal, [$($args: tt)*], [$($names: ident)*], $key: ident = $value: expr) => {
+        $crate::format!(@ $f, $fmt, [$($args)* (stringify!($key), $value).into_argument(),], [$($names)* $key],)
     };
-    (@ $fmt: literal, [$($args: tt)*], $value: expr) => {
-        {
-            use $crate::IntoArgument;
-            $crate::formatter($fmt, ($($args)*, ($value).into_argument(),))
-        }
+    (@ $f: ident, $fmt: literal, [$($args: tt)*], [$($names: ident)*], $value: expr) => {
+        $crate::format!(@ $f, $fmt, [$($args)* ($value).into_argument(),], [$($names)*],)
     };
-    (@ $fmt: literal, [$($args: tt)*], $(,)?) => {
+    (@ $f: ident, $fmt: literal, [$($args: tt)*], [$($names: ident)*], $(,)?) => {
         {
+            #[allow(unused_imports)]
             use $crate::IntoArgument;
-            $crate::formatter($fmt, ($($args)*))
+            $crate::$f($fmt, &$crate::__capture!($fmt, [$($args)*], [$($names)*]))
         }
     };
-}
\ No newline at end of file
+}
+
+/// Same as [`format!`] but returns a `Result<String, FormatError>` instead of panicking on a
+/// malformed template.
+#[macro_export]
+macro_rules! try_format {
+    ($fmt: literal) => {
+        $crate::format!(@ try_formatter, $fmt, [], [],)
+    };
+    ($fmt: literal, $($args: tt)*) => {
+        $crate::format!(@ try_formatter, $fmt, [], [], $($args)*)
+    };
+}
@@ -0,0 +1,137 @@
+use format::{try_format, try_formatter, Argument, FormatError, IntoArgument};
+
+/// Stand in for a style argument that wraps text in brackets.
+struct Bracket;
+
+impl Argument for Bracket {
+    fn render(&self) -> String {
+        String::new()
+    }
+
+    fn style(&self) -> Option<(String, String)> {
+        Some(("[".to_string(), "]".to_string()))
+    }
+}
+
+#[test]
+fn unmatched_brace() {
+    assert_eq!(try_format!("{", 1), Err(FormatError::UnmatchedBrace { position: 0 }));
+    assert_eq!(try_format!("a}"), Err(FormatError::UnmatchedBrace { position: 1 }));
+}
+
+#[test]
+fn unknown_named_argument() {
+    // The macro captures bare identifiers from scope, so only the formatter can miss a name
+    let args = [1.into_argument(), ("other", 2).into_argument()];
+    assert_eq!(try_formatter("{0} {name}", &args), Err(FormatError::UnknownNamedArgument("name".to_string())));
+}
+
+#[test]
+fn index_out_of_range() {
+    assert_eq!(try_format!("{} {}", 1), Err(FormatError::IndexOutOfRange { index: 1, count: 1 }));
+}
+
+#[test]
+fn unsupported_format() {
+    assert_eq!(try_format!("{:e}", "text"), Err(FormatError::UnsupportedFormat { spec: "e".to_string() }));
+}
+
+#[test]
+fn invalid_spec() {
+    assert_eq!(try_format!("{:x}", 1), Err(FormatError::InvalidSpec { spec: "x".to_string() }));
+}
+
+#[test]
+fn invalid_count() {
+    assert_eq!(try_format!("{:1$}", "a", "b"), Err(FormatError::InvalidCount("1".to_string())));
+}
+
+#[test]
+fn unbalanced_style() {
+    assert_eq!(try_format!("{b:style}x{b:/style}", b = Bracket), Ok("[x]".to_string()));
+    assert_eq!(try_format!("{b:style}x", b = Bracket), Err(FormatError::UnbalancedStyle));
+    assert_eq!(try_format!("x{:/style}"), Err(FormatError::UnbalancedStyle));
+    assert_eq!(try_format!("{b:style}x{c:/style}", b = Bracket, c = Bracket), Err(FormatError::UnbalancedStyle));
+}
+
+#[test]
+#[allow(clippy::approx_constant)]
+fn dynamic_width_and_precision() {
+    assert_eq!(try_format!("{:w$.p$}", 3.14159, w = 8, p = 2), Ok("    3.14".to_string()));
+    assert_eq!(try_format!("{:w$.p$}", 3.14159, w = -8, p = 2), Err(FormatError::InvalidCount("w".to_string())));
+    assert_eq!(try_format!("{:w$.p$}", 3.14159, w = 8, p = 2.5), Err(FormatError::InvalidCount("p".to_string())));
+    assert_eq!(try_format!("{:w$}", 1, w = "8"), Err(FormatError::InvalidCount("w".to_string())));
+}
+
+#[test]
+fn mixed_positional_and_named_arguments() {
+    assert_eq!(format::format!("{}", 1), "1");
+    assert_eq!(format::format!("{a}", a = 1), "1");
+    assert_eq!(format::format!("{} {a}", 1, a = 2), "1 2");
+    assert_eq!(format::format!("{a} {}", a = 1, 2), "1 2");
+    assert_eq!(format::format!("{} {a} {}", 1, a = 2, 3), "1 2 3");
+    assert_eq!(format::format!("{a} {} {b}", a = 1, 2, b = 3), "1 2 3");
+    assert_eq!(format::format!("{} {} {a}", 1, 2, a = 3,), "1 2 3");
+}
+
+#[test]
+fn formatter_placeholders() {
+    let args = [1.into_argument(), ("name", "value").into_argument()];
+    assert_eq!(try_formatter("a {} b", &args), Ok("a 1 b".to_string()));
+    assert_eq!(try_formatter("{name}", &args), Ok("value".to_string()));
+    assert_eq!(try_formatter("{{}} {{name}}", &args), Ok("{} {name}".to_string()));
+    assert_eq!(try_formatter("{} {", &args), Err(FormatError::UnmatchedBrace { position: 3 }));
+}
+
+#[test]
+fn captures_from_scope() {
+    let name = "x";
+    assert_eq!(format::format!("{name}"), "x");
+    assert_eq!(format::format!("{name}", name = "y"), "y");
+    assert_eq!(format::format!("{name} {}", 1), "x 1");
+
+    let width = 4;
+    assert_eq!(format::format!("{:width$}|", "a"), "a   |");
+    assert_eq!(format::format!("{:*>width$}", "a"), "***a");
+    assert_eq!(format::format!("{name:width$}|"), "x   |");
+}
+
+#[test]
+fn width_and_alignment() {
+    assert_eq!(format::format!("{:>10}", "right"), "     right");
+    assert_eq!(format::format!("{:<10}|", "left"), "left      |");
+    assert_eq!(format::format!("{:*^20}", "center"), "*******center*******");
+    assert_eq!(format::format!("{:2}", "longer"), "longer");
+    assert_eq!(format::format!("{:5}", 1), "    1");
+    // Padded by display columns rather than bytes or chars
+    assert_eq!(format::format!("{:>4}", "日本"), "日本");
+    assert_eq!(format::format!("{:-^6}", "日本"), "-日本-");
+    assert_eq!(format::format!("{:>3}", "\x1b[1mx\x1b[22m"), "  \x1b[1mx\x1b[22m");
+}
+
+#[test]
+fn string_char_and_bool_arguments() {
+    assert_eq!(format::format!("{} {} {}", String::from("text"), 'c', true), "text c true");
+    assert_eq!(format::format!("{:>3}|{:^5}|{:.2}", 'c', false, String::from("text")), "  c|false|te");
+}
+
+#[test]
+fn scientific_notation() {
+    assert_eq!(format::format!("{:e}", 1234.5), "1.2345e3");
+    assert_eq!(format::format!("{:E}", 1234.5), "1.2345E3");
+    assert_eq!(format::format!("{:e}", 1500), "1.5e3");
+}
+
+#[test]
+fn named_width_and_precision() {
+    assert_eq!(format::format!("{v:w$}|", v = "x", w = 5), "x    |");
+    assert_eq!(format::format!("{v:>w$.p$}", v = 1.23456, w = 6, p = 2), "  1.23");
+    assert_eq!(try_format!("{v:w$}", v = "x", w = "5"), Err(FormatError::InvalidCount("w".to_string())));
+}
+
+#[test]
+fn styled_arguments_pad_by_visible_width() {
+    let styled = "\x1b[1;31mx\x1b[22;39m";
+    assert_eq!(format::format!("{:>5}", styled), "    \x1b[1;31mx\x1b[22;39m");
+    assert_eq!(format::format!("{:<5}|", styled), "\x1b[1;31mx\x1b[22;39m    |");
+}
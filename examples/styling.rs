@@ -101,6 +101,7 @@ fn main() -> std::io::Result<()> {
         fg: Some(color!(220, 100, 50)),
         bg: Some(color!(243)),
         link: Some(Hyperlink::from("https://example.com")),
+        ..Style::default()
     };
     println!("{style}All Together (Style){style:-}");
 
@@ -112,6 +113,10 @@ fn main() -> std::io::Result<()> {
         .fg(color!(yellow));
     println!("{style}All Together (Style): builder{style:-}");
 
+    // Flags can also be added to an existing style with `+`
+    let style = Style::builder().fg(color!(red)) + BOLD + ITALIC;
+    println!("{style}All Together (Style): add{style:-}");
+
     /*
         Capabilities:
             The library also provides terminal capabilities like whether it supports ansi sequences, and
@@ -0,0 +1,179 @@
+use std::iter::FusedIterator;
+
+/// A single piece of a string containing ansi escape sequences.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AnsiToken<'a> {
+    /// Plain text without any escape sequences
+    Text(&'a str),
+    /// Select graphic rendition parameters; `\x1b[1;31m` == `Sgr(vec![1, 31])`.
+    ///
+    /// Empty parameters default to `0` so `\x1b[m` == `Sgr(vec![0])`.
+    Sgr(Vec<u16>),
+    /// `\x1b]8;;{url}\x1b\\`. An empty url closes the hyperlink.
+    OscHyperlink { url: &'a str },
+    /// Any other operating system command: `\x1b]{0}\x1b\\`
+    Osc(&'a str),
+    /// Any other control sequence: `\x1b[{params}{action}`
+    Csi { params: &'a str, action: char },
+    /// Two character escape sequences: `\x1b{0}`
+    Escape(char),
+}
+
+/// Iterator that splits a string into text and ansi escape sequences without buffering.
+///
+/// The tokenizer never splits an escape sequence. If the input ends part way through a sequence
+/// the iterator stops and [`AnsiTokenizer::remainder`] returns the incomplete sequence. When
+/// feeding a stream in chunks, prepend the remainder to the next chunk before tokenizing it.
+#[derive(Debug, Clone)]
+pub struct AnsiTokenizer<'a> {
+    source: &'a str,
+    position: usize,
+}
+
+impl<'a> AnsiTokenizer<'a> {
+    pub fn new(source: &'a str) -> Self {
+        AnsiTokenizer { source, position: 0 }
+    }
+
+    /// The part of the source that has not been tokenized yet.
+    ///
+    /// After the iterator is exhausted this is either empty or an incomplete escape sequence.
+    pub fn remainder(&self) -> &'a str {
+        &self.source[self.position..]
+    }
+
+    /// Parse the escape sequence at the start of `rest` returning the token and its byte length.
+    fn escape(rest: &'a str) -> Option<(AnsiToken<'a>, usize)> {
+        let bytes = rest.as_bytes();
+        match *bytes.get(1)? {
+            b'[' => {
+                let end = bytes[2..].iter().position(|b| (0x40..=0x7E).contains(b))? + 2;
+                let params = &rest[2..end];
+                let action = bytes[end] as char;
+                let token = match action {
+                    'm' => match parse_sgr(params) {
+                        Some(codes) => AnsiToken::Sgr(codes),
+                        None => AnsiToken::Csi { params, action },
+                    },
+                    _ => AnsiToken::Csi { params, action },
+                };
+                Some((token, end + 1))
+            }
+            b']' => {
+                // Terminated by either ST (`\x1b\\`) or BEL
+                let (end, terminator) = bytes[2..]
+                    .iter()
+                    .enumerate()
+                    .find_map(|(i, b)| match b {
+                        0x07 => Some((i + 2, 1)),
+                        0x1b => Some((i + 2, 2)),
+                        _ => None,
+                    })?;
+                if terminator == 2 && *bytes.get(end + 1)? != b'\\' {
+                    return Some((AnsiToken::Osc(&rest[2..end]), end));
+                }
+
+                let command = &rest[2..end];
+                let token = match command.strip_prefix("8;") {
+                    Some(link) => match link.split_once(';') {
+                        Some((_, url)) => AnsiToken::OscHyperlink { url },
+                        None => AnsiToken::Osc(command),
+                    },
+                    None => AnsiToken::Osc(command),
+                };
+                Some((token, end + terminator))
+            }
+            _ => {
+                let c = rest[1..].chars().next()?;
+                Some((AnsiToken::Escape(c), 1 + c.len_utf8()))
+            }
+        }
+    }
+}
+
+/// Parse `;` or `:` separated sgr parameters where empty parameters are `0`.
+fn parse_sgr(params: &str) -> Option<Vec<u16>> {
+    params
+        .split([';', ':'])
+        .map(|p| if p.is_empty() { Some(0) } else { p.parse::<u16>().ok() })
+        .collect()
+}
+
+impl<'a> Iterator for AnsiTokenizer<'a> {
+    type Item = AnsiToken<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let rest = self.remainder();
+        if rest.is_empty() {
+            return None;
+        }
+
+        if rest.starts_with('\x1b') {
+            let (token, len) = Self::escape(rest)?;
+            self.position += len;
+            Some(token)
+        } else {
+            let len = rest.find('\x1b').unwrap_or(rest.len());
+            self.position += len;
+            Some(AnsiToken::Text(&rest[..len]))
+        }
+    }
+}
+
+impl FusedIterator for AnsiTokenizer<'_> {}
+
+/// Number of terminal columns the string takes up when printed, ignoring the non-printing bytes.
+///
+/// Sgr sequences, hyperlinks, and every other escape sequence take no columns, only the text
+/// between them is measured by its unicode width. An incomplete escape sequence at the end of the
+/// string, Ex: `"text\x1b[3"`, is not printed as text either so it takes no columns.
+///
+/// Same as [`format::visible_width`] which the format crate pads by.
+pub fn printable_width(s: &str) -> usize {
+    format::visible_width(s)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn printable_width_skips_escapes() {
+        assert_eq!(printable_width("\x1b[1;31mred\x1b[0m"), 3);
+        assert_eq!(printable_width("\x1b]8;;https://x.y\x1b\\link\x1b]8;;\x1b\\ 日本"), 9);
+        assert_eq!(printable_width("a\x1b[3"), 1);
+    }
+
+    #[test]
+    fn tokenize_mixed_string() {
+        let tokens = AnsiTokenizer::new("a\x1b[1;31mb\x1b[m\x1b]8;;https://x.y\x1b\\c\x1b]8;;\x07\x1b[2J\x1b7d").collect::<Vec<_>>();
+        assert_eq!(
+            tokens,
+            [
+                AnsiToken::Text("a"),
+                AnsiToken::Sgr(vec![1, 31]),
+                AnsiToken::Text("b"),
+                AnsiToken::Sgr(vec![0]),
+                AnsiToken::OscHyperlink { url: "https://x.y" },
+                AnsiToken::Text("c"),
+                AnsiToken::OscHyperlink { url: "" },
+                AnsiToken::Csi { params: "2", action: 'J' },
+                AnsiToken::Escape('7'),
+                AnsiToken::Text("d"),
+            ]
+        );
+    }
+
+    #[test]
+    fn tokenize_across_chunks() {
+        let mut tokenizer = AnsiTokenizer::new("a\x1b[1;3");
+        assert_eq!(tokenizer.by_ref().collect::<Vec<_>>(), [AnsiToken::Text("a")]);
+        assert_eq!(tokenizer.remainder(), "\x1b[1;3");
+        assert_eq!(tokenizer.next(), None);
+
+        let next = format!("{}1mb", tokenizer.remainder());
+        let mut tokenizer = AnsiTokenizer::new(&next);
+        assert_eq!(tokenizer.by_ref().collect::<Vec<_>>(), [AnsiToken::Sgr(vec![1, 31]), AnsiToken::Text("b")]);
+        assert_eq!(tokenizer.remainder(), "");
+    }
+}
@@ -1,9 +1,12 @@
 use std::fmt::Display;
-use std::ops::{BitAnd, BitAndAssign, BitOr, BitOrAssign};
+use std::ops::{BitAnd, BitAndAssign, BitOr, BitOrAssign, BitXor, BitXorAssign, Not};
 
 use crate::style::AnsiSequence;
 
 /// Flags representing the style of the text.
+///
+/// With the `serde` feature the flags serialize as a list of names, Ex: `["bold", "italic"]`,
+/// or as the raw bitmask with `#[serde(with = "flags::bitmask")]`. Both forms deserialize.
 #[derive(Default, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct StyleFlag(pub u32);
 
@@ -28,34 +31,78 @@ impl AnsiSequence for StyleFlag {
         if *self & REVERSED == REVERSED {
             ansi.push("7");
         }
+        if *self & DIM == DIM {
+            ansi.push("2");
+        }
+        if *self & DOUBLE_UNDERLINE == DOUBLE_UNDERLINE {
+            ansi.push("21");
+        }
+        if *self & RAPID_BLINK == RAPID_BLINK {
+            ansi.push("6");
+        }
+        if *self & HIDDEN == HIDDEN {
+            ansi.push("8");
+        }
         ansi.join(";")
     }
 
     fn reset_ansi(&self) -> String {
-        let mut ansi = Vec::new();
         if *self & RESET == RESET {
             return "0".to_string();
         }
 
-        if *self & BOLD == BOLD {
-            ansi.push("22");
-        }
-        if *self & ITALIC == ITALIC {
-            ansi.push("23");
-        }
-        if *self & UNDERLINE == UNDERLINE {
-            ansi.push("24");
-        }
-        if *self & CROSSED == CROSSED {
-            ansi.push("29");
-        }
-        if *self & BLINK == BLINK {
-            ansi.push("25");
-        }
-        if *self & REVERSED == REVERSED {
-            ansi.push("27");
+        RESET_FAMILIES
+            .iter()
+            .filter(|(family, _)| (*self & *family).0 > 0)
+            .map(|(_, reset)| *reset)
+            .collect::<Vec<_>>()
+            .join(";")
+    }
+}
+
+impl StyleFlag {
+    /// No flags set.
+    pub const fn empty() -> StyleFlag {
+        StyleFlag(0)
+    }
+
+    /// Clear the given flags, Ex: `style.flags.remove(BOLD)`. Removing flags that aren't set
+    /// does nothing.
+    ///
+    /// Unlike `self & !other`, `RESET` is only cleared when it is one of the removed flags.
+    pub fn remove(self, other: StyleFlag) -> StyleFlag {
+        StyleFlag(self.0 & !other.0)
+    }
+
+    /// Every flag that is cleared along with these flags by their reset codes, Ex: resetting
+    /// `DIM` with `22` also clears `BOLD`.
+    pub fn reset_family(&self) -> StyleFlag {
+        RESET_FAMILIES
+            .iter()
+            .filter(|(family, _)| (*self & *family).0 > 0)
+            .fold(StyleFlag(0), |flags, (family, _)| flags | *family)
+    }
+
+    /// Iterate the attribute flags that are set, from the lowest bit to the highest. `RESET` is
+    /// not an attribute and is never yielded.
+    pub fn iter(&self) -> impl Iterator<Item = StyleFlag> {
+        let flags = *self;
+        ATTRIBUTES.into_iter().filter(move |flag| flags & *flag == *flag)
+    }
+
+    /// Human readable summary of the flags, Ex: `bold+italic`. Empty flags are `none`.
+    pub fn describe(&self) -> String {
+        let flags = NAMES
+            .iter()
+            .filter(|(flag, _)| *self & *flag == *flag)
+            .map(|(_, name)| name.replace('_', " "))
+            .collect::<Vec<_>>();
+
+        if flags.is_empty() {
+            "none".to_string()
+        } else {
+            flags.join("+")
         }
-        ansi.join(";")
     }
 }
 
@@ -73,6 +120,22 @@ impl BitAnd for StyleFlag {
     }
 }
 
+/// Inverts only the attribute bits, the result never contains `RESET` or unassigned bits.
+impl Not for StyleFlag {
+    type Output = StyleFlag;
+    fn not(self) -> Self::Output {
+        StyleFlag(!self.0 & VALID_MASK.0)
+    }
+}
+
+/// Toggles the flags, so applying the same flags twice is a no-op.
+impl BitXor for StyleFlag {
+    type Output = StyleFlag;
+    fn bitxor(self, rhs: Self) -> Self::Output {
+        StyleFlag(self.0 ^ rhs.0)
+    }
+}
+
 impl BitAndAssign for StyleFlag {
     fn bitand_assign(&mut self, rhs: Self) {
         self.0 &= rhs.0
@@ -85,6 +148,37 @@ impl BitOrAssign for StyleFlag {
     }
 }
 
+impl BitXorAssign for StyleFlag {
+    fn bitxor_assign(&mut self, rhs: Self) {
+        self.0 ^= rhs.0
+    }
+}
+
+/*
+    Bit layout:
+        Attributes are assigned single bits from the lowest bit up, in the order they are added.
+        The `RESET` sentinel is not an attribute and is kept at the highest bit so the attributes
+        can grow without colliding with it. New attributes must take the next unused low bit and
+        be added to `ATTRIBUTES`.
+
+        Bit 6 is unused since `RESET` was there before it moved to bit 31, so an old bitmask
+        with `RESET` set isn't read as some other attribute.
+
+        0  BOLD
+        1  ITALIC
+        2  UNDERLINE
+        3  CROSSED
+        4  BLINK
+        5  REVERSED
+        6  unused, the old `RESET`
+        7  DIM
+        8  DOUBLE_UNDERLINE
+        9  RAPID_BLINK
+        10 HIDDEN
+        .. reserved for new attributes
+        31 RESET
+*/
+
 /// `\x1b[1m` - `\x1b[22m`
 pub const BOLD: StyleFlag = StyleFlag(1u32);
 
@@ -103,9 +197,76 @@ pub const BLINK: StyleFlag = StyleFlag(16u32);
 /// `\x1b[7m` - `\x1b[27m`
 pub const REVERSED: StyleFlag = StyleFlag(32u32);
 
+/// Faint text. Shares its reset with `BOLD`.
+/// `\x1b[2m` - `\x1b[22m`
+pub const DIM: StyleFlag = StyleFlag(128u32);
+
+/// Shares its reset with `UNDERLINE`.
+/// `\x1b[21m` - `\x1b[24m`
+pub const DOUBLE_UNDERLINE: StyleFlag = StyleFlag(256u32);
+
+/// Shares its reset with `BLINK`.
+/// `\x1b[6m` - `\x1b[25m`
+pub const RAPID_BLINK: StyleFlag = StyleFlag(512u32);
+
+/// Concealed text, Ex: password fields.
+/// `\x1b[8m` - `\x1b[28m`
+pub const HIDDEN: StyleFlag = StyleFlag(1024u32);
+
 /// *ONLY* added the reset sequence.
 /// `\x1b[0m`
-pub const RESET: StyleFlag = StyleFlag(64u32);
+pub const RESET: StyleFlag = StyleFlag(1u32 << 31);
+
+/// Every flag with its name, the attributes from the lowest bit to the highest and `RESET` last.
+/// The names are the serialized form and, with spaces for underscores, what
+/// [`StyleFlag::describe`] writes.
+const NAMES: [(StyleFlag, &str); 11] = [
+    (BOLD, "bold"),
+    (ITALIC, "italic"),
+    (UNDERLINE, "underline"),
+    (CROSSED, "crossed"),
+    (BLINK, "blink"),
+    (REVERSED, "reversed"),
+    (DIM, "dim"),
+    (DOUBLE_UNDERLINE, "double_underline"),
+    (RAPID_BLINK, "rapid_blink"),
+    (HIDDEN, "hidden"),
+    (RESET, "reset"),
+];
+
+/// Every attribute flag, excluding `RESET`, from the lowest bit to the highest.
+pub const ATTRIBUTES: [StyleFlag; 10] = {
+    let mut attributes = [StyleFlag(0); 10];
+    let mut i = 0;
+    while i < attributes.len() {
+        attributes[i] = NAMES[i].0;
+        i += 1;
+    }
+    attributes
+};
+
+/// Mask of every attribute flag, excluding `RESET`.
+pub const VALID_MASK: StyleFlag = {
+    let mut mask = 0;
+    let mut i = 0;
+    while i < ATTRIBUTES.len() {
+        mask |= ATTRIBUTES[i].0;
+        i += 1;
+    }
+    StyleFlag(mask)
+};
+
+/// Attributes that are cleared together by the same reset code. There is no code to clear only
+/// one member of a family, so turning one off clears the whole family.
+const RESET_FAMILIES: [(StyleFlag, &str); 7] = [
+    (StyleFlag(BOLD.0 | DIM.0), "22"),
+    (ITALIC, "23"),
+    (StyleFlag(UNDERLINE.0 | DOUBLE_UNDERLINE.0), "24"),
+    (CROSSED, "29"),
+    (StyleFlag(BLINK.0 | RAPID_BLINK.0), "25"),
+    (REVERSED, "27"),
+    (HIDDEN, "28"),
+];
 
 impl Display for StyleFlag {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -115,4 +276,141 @@ impl Display for StyleFlag {
             write!(f, "{}", self.sequence())
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for StyleFlag {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeSeq;
+
+        let names = NAMES.iter().filter(|(flag, _)| *self & *flag == *flag).collect::<Vec<_>>();
+        let mut seq = serializer.serialize_seq(Some(names.len()))?;
+        for (_, name) in names {
+            seq.serialize_element(name)?;
+        }
+        seq.end()
+    }
+}
+
+/// Accepts either a list of names or a bitmask. Unknown names and bits are an error.
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for StyleFlag {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct FlagVisitor;
+
+        impl<'de> serde::de::Visitor<'de> for FlagVisitor {
+            type Value = StyleFlag;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                write!(f, "a list of style flag names or a bitmask")
+            }
+
+            fn visit_u64<E: serde::de::Error>(self, value: u64) -> Result<Self::Value, E> {
+                match u32::try_from(value) {
+                    Ok(bits) if bits & !(VALID_MASK.0 | RESET.0) == 0 => Ok(StyleFlag(bits)),
+                    _ => Err(E::custom(format!("unknown style flag bits in {:#x}", value))),
+                }
+            }
+
+            fn visit_seq<A: serde::de::SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+                let mut flags = StyleFlag(0);
+                while let Some(name) = seq.next_element::<String>()? {
+                    match NAMES.iter().find(|(_, n)| *n == name) {
+                        Some((flag, _)) => flags |= *flag,
+                        None => return Err(serde::de::Error::custom(format!("unknown style flag `{}`", name))),
+                    }
+                }
+                Ok(flags)
+            }
+        }
+
+        deserializer.deserialize_any(FlagVisitor)
+    }
+}
+
+/// Serialize the flags as their raw `u32` bitmask for compact storage,
+/// Ex: `#[serde(with = "mark_rs::style::flags::bitmask")]`. Deserializing accepts both forms.
+#[cfg(feature = "serde")]
+pub mod bitmask {
+    use super::StyleFlag;
+
+    pub fn serialize<S: serde::Serializer>(flags: &StyleFlag, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u32(flags.0)
+    }
+
+    pub fn deserialize<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<StyleFlag, D::Error> {
+        serde::Deserialize::deserialize(deserializer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn describe_uses_the_flag_names() {
+        assert_eq!((BOLD | DOUBLE_UNDERLINE | RESET).describe(), "bold+double underline+reset");
+        assert_eq!(BOLD.describe(), "bold");
+        assert_eq!(RESET.describe(), "reset");
+        assert_eq!(StyleFlag::empty().describe(), "none");
+        assert_eq!(ATTRIBUTES.len() + 1, NAMES.len());
+        assert_eq!(VALID_MASK, StyleFlag(0x7bf));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn names_round_trip() {
+        let flags = BOLD | DOUBLE_UNDERLINE | RAPID_BLINK | RESET;
+        let json = serde_json::to_string(&flags).unwrap();
+        assert_eq!(json, r#"["bold","double_underline","rapid_blink","reset"]"#);
+        assert_eq!(serde_json::from_str::<StyleFlag>(&json).unwrap(), flags);
+        assert!(serde_json::from_str::<StyleFlag>(r#"["bold","double underline"]"#).is_err());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn bitmask_round_trip() {
+        #[derive(serde::Serialize, serde::Deserialize)]
+        struct Packed(#[serde(with = "bitmask")] StyleFlag);
+
+        let flags = ITALIC | HIDDEN | RESET;
+        let json = serde_json::to_string(&Packed(flags)).unwrap();
+        assert_eq!(json, (ITALIC | HIDDEN | RESET).0.to_string());
+        assert_eq!(serde_json::from_str::<Packed>(&json).unwrap().0, flags);
+        assert!(serde_json::from_str::<StyleFlag>(&(1u32 << 6).to_string()).is_err());
+        assert!(serde_json::from_str::<StyleFlag>(&(1u32 << 11).to_string()).is_err());
+    }
+
+    #[test]
+    fn remove_and_toggle() {
+        let flags = BOLD | ITALIC | RESET;
+        assert_eq!(flags.remove(BOLD), ITALIC | RESET);
+        assert_eq!(flags.remove(UNDERLINE), flags);
+        assert_eq!(flags.remove(RESET), BOLD | ITALIC);
+        // `!` only covers the attributes, so `& !` always clears RESET
+        assert_eq!(flags & !BOLD, ITALIC);
+
+        assert_eq!(flags ^ BOLD, ITALIC | RESET);
+        assert_eq!(flags ^ RESET, BOLD | ITALIC);
+        assert_eq!(flags ^ UNDERLINE ^ UNDERLINE, flags);
+
+        let mut toggled = flags;
+        toggled ^= BOLD | RESET;
+        assert_eq!(toggled, ITALIC);
+        toggled ^= BOLD | RESET;
+        assert_eq!(toggled, flags);
+    }
+
+    #[test]
+    fn flags_are_distinct_bits_in_the_mask() {
+        let mut seen = StyleFlag::empty();
+        for flag in ATTRIBUTES {
+            assert_eq!(flag.0.count_ones(), 1, "{:?}", flag);
+            assert_eq!(seen & flag, StyleFlag::empty(), "{:?}", flag);
+            assert_eq!(VALID_MASK & flag, flag);
+            seen |= flag;
+        }
+        assert_eq!(seen, VALID_MASK);
+        assert_eq!(VALID_MASK & RESET, StyleFlag::empty());
+    }
+}
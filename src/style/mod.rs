@@ -1,25 +1,50 @@
 use std::collections::hash_map::DefaultHasher;
-use std::fmt::{Debug, Display, Formatter};
+use std::fmt::{Alignment, Debug, Display, Formatter, Write};
 use std::hash::{Hash, Hasher};
 use std::iter;
+use std::ops::Add;
+use std::path::{Component, Path, Prefix};
+use std::str::FromStr;
+use unicode_width::UnicodeWidthStr;
 
 pub use crate::_color as color;
-use crate::style::flags::{BLINK, BOLD, CROSSED, ITALIC, RESET, REVERSED, StyleFlag, UNDERLINE};
+use crate::style::ansi::{AnsiToken, AnsiTokenizer};
+use crate::style::flags::{
+    BLINK, BOLD, CROSSED, DIM, DOUBLE_UNDERLINE, HIDDEN, ITALIC, RAPID_BLINK, RESET, REVERSED, StyleFlag, UNDERLINE,
+};
+use crate::terminal::{Capabilities, ColorSupport, CAPABILITIES};
 
+pub mod ansi;
 pub mod flags;
+pub mod plain;
 
 pub trait AnsiSequence {
     fn ansi(&self) -> String;
+    /// The full escape sequence. Empty when there is no ansi since `\x1b[m` would reset
+    /// every attribute.
     fn sequence(&self) -> String {
-        format!("\x1b[{}m", self.ansi())
+        let ansi = self.ansi();
+        if ansi.is_empty() {
+            String::new()
+        } else {
+            format!("\x1b[{}m", ansi)
+        }
     }
     fn reset_ansi(&self) -> String;
+    /// The full reset escape sequence. Empty when there is no reset ansi since `\x1b[m` would
+    /// reset every attribute.
     fn reset_sequence(&self) -> String {
-        format!("\x1b[{}m", self.reset_ansi())
+        let ansi = self.reset_ansi();
+        if ansi.is_empty() {
+            String::new()
+        } else {
+            format!("\x1b[{}m", ansi)
+        }
     }
 }
 
 #[derive(Default, Debug, Clone, Hash, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Hyperlink(pub String);
 impl Display for Hyperlink {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
@@ -31,6 +56,153 @@ impl Display for Hyperlink {
     }
 }
 
+impl Hyperlink {
+    /// Build a url from its components, Ex: `Hyperlink::new("file", "", "/path with space/x.rs")`
+    /// == `file:///path%20with%20space/x.rs`.
+    ///
+    /// The path is percent encoded.
+    pub fn new<S: Display, H: Display, P: Display>(scheme: S, host: H, path: P) -> Self {
+        Hyperlink::builder().scheme(scheme).host(host).path(path).build()
+    }
+
+    pub fn builder() -> HyperlinkBuilder {
+        HyperlinkBuilder::default()
+    }
+
+    /// A `file://` url to a filesystem path so editors and terminals can open it,
+    /// Ex: `Hyperlink::from_path(Path::new("src/lib.rs"), Some(Path::new("/repo")))` ==
+    /// `file:///repo/src/lib.rs`.
+    ///
+    /// Relative paths are resolved against `base`, or the current directory when there is no
+    /// base. The path is canonicalized when it exists, otherwise `.` and `..` are resolved without
+    /// touching the filesystem. Windows drive paths become `file:///C:/...` and UNC paths use the
+    /// server as the host.
+    ///
+    /// # Errors
+    /// If the current directory is needed to resolve the path and can't be read.
+    pub fn from_path(path: &Path, base: Option<&Path>) -> std::io::Result<Self> {
+        let mut absolute = match base {
+            Some(base) if path.is_relative() => base.join(path),
+            _ => path.to_path_buf(),
+        };
+        if absolute.is_relative() {
+            absolute = std::env::current_dir()?.join(absolute);
+        }
+        let absolute = std::fs::canonicalize(&absolute).unwrap_or(absolute);
+
+        let mut host = String::new();
+        let mut segments: Vec<String> = Vec::new();
+        let mut root = 0;
+        for component in absolute.components() {
+            match component {
+                Component::Prefix(prefix) => {
+                    match prefix.kind() {
+                        Prefix::Disk(drive) | Prefix::VerbatimDisk(drive) => segments.push(format!("{}:", drive as char)),
+                        Prefix::UNC(server, share) | Prefix::VerbatimUNC(server, share) => {
+                            host = server.to_string_lossy().into_owned();
+                            segments.push(share.to_string_lossy().into_owned());
+                        }
+                        _ => segments.push(prefix.as_os_str().to_string_lossy().into_owned()),
+                    }
+                    root = segments.len();
+                }
+                Component::RootDir | Component::CurDir => {}
+                Component::ParentDir => {
+                    // Never pop the drive or share
+                    if segments.len() > root {
+                        segments.pop();
+                    }
+                }
+                Component::Normal(name) => segments.push(name.to_string_lossy().into_owned()),
+            }
+        }
+
+        Ok(Hyperlink::builder()
+            .scheme("file")
+            .host(host)
+            .path(format!("/{}", segments.join("/")))
+            .build())
+    }
+}
+
+/// Percent encode everything except unreserved characters and the extra allowed characters.
+fn percent_encode(value: &str, allowed: &[u8]) -> String {
+    let mut encoded = String::new();
+    for byte in value.bytes() {
+        if byte.is_ascii_alphanumeric() || b"-._~".contains(&byte) || allowed.contains(&byte) {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    encoded
+}
+
+/// Builder that assembles and percent encodes the components of a [`Hyperlink`] url.
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+pub struct HyperlinkBuilder {
+    scheme: String,
+    host: String,
+    path: String,
+    query: Vec<(String, String)>,
+    fragment: Option<String>,
+}
+
+impl HyperlinkBuilder {
+    pub fn scheme<S: Display>(mut self, scheme: S) -> Self {
+        self.scheme = scheme.to_string();
+        self
+    }
+
+    pub fn host<H: Display>(mut self, host: H) -> Self {
+        self.host = host.to_string();
+        self
+    }
+
+    pub fn path<P: Display>(mut self, path: P) -> Self {
+        self.path = path.to_string();
+        self
+    }
+
+    /// Add a `key=value` pair to the query string
+    pub fn query<K: Display, V: Display>(mut self, key: K, value: V) -> Self {
+        self.query.push((key.to_string(), value.to_string()));
+        self
+    }
+
+    pub fn fragment<F: Display>(mut self, fragment: F) -> Self {
+        self.fragment = Some(fragment.to_string());
+        self
+    }
+
+    pub fn build(self) -> Hyperlink {
+        let mut url = String::new();
+        if !self.scheme.is_empty() {
+            url.push_str(&self.scheme);
+            url.push_str("://");
+        }
+        url.push_str(&percent_encode(&self.host, b":@[]"));
+        if !self.path.is_empty() && !self.path.starts_with('/') {
+            url.push('/');
+        }
+        url.push_str(&percent_encode(&self.path, b"/:@"));
+
+        if !self.query.is_empty() {
+            let query = self.query
+                .iter()
+                .map(|(k, v)| format!("{}={}", percent_encode(k, b""), percent_encode(v, b"")))
+                .collect::<Vec<_>>();
+            url.push('?');
+            url.push_str(&query.join("&"));
+        }
+        if let Some(fragment) = &self.fragment {
+            url.push('#');
+            url.push_str(&percent_encode(fragment, b"/:@"));
+        }
+        Hyperlink(url)
+    }
+}
+
 impl From<&str> for Hyperlink {
     fn from(d: &str) -> Self {
         Hyperlink(d.to_string())
@@ -64,8 +236,20 @@ impl AnsiSequence for Hyperlink {
 /// Terminal color representation.
 ///
 /// Supports named system colors, XTerm/Ansi colors (0-255), and RGB colors (0-255,0-255,0-255).
-#[derive(Debug, Clone, Copy, PartialEq)]
+///
+/// Named colors are the same color as their xterm index, `Color::RED == Color::Ansi(1)` and
+/// `Color::BrightRed == Color::Ansi(9)`, so they compare and hash equal. The first 16 xterm
+/// colors render the same sgr codes as the named colors, `31` and `91`, so equal colors always
+/// render the same sequence.
+///
+/// The float variants, HSL, HSV, HWB, CYMK, and Oklch, compare and hash by the rounded RGB they render
+/// so tiny float drift, Ex: `0.5` and `0.5000001`, doesn't make them different colors.
+#[derive(Default, Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Color {
+    /// The terminal's default color. Same as the reset sequence; `39` for fg and `49` for bg.
+    #[default]
+    Default,
     BLACK,
     RED,
     GREEN,
@@ -74,59 +258,86 @@ pub enum Color {
     MAGENTA,
     CYAN,
     WHITE,
+    BrightBlack,
+    BrightRed,
+    BrightGreen,
+    BrightYellow,
+    BrightBlue,
+    BrightMagenta,
+    BrightCyan,
+    BrightWhite,
     /// 0<=value<=255
     Ansi(u8),
     /// 0<=R<=255, 0<=G<=255, 0<=B<=255
     RGB { r: u8, g: u8, b: u8 },
-    /// 0<=H<360, 0<=S<=1, 0<=L<=1
+    /// RGB with an alpha channel, 0<=A<=255. Terminals can't render alpha, see [`Color::composite`].
+    /// A fully transparent color, alpha 0, renders as the terminal's default color.
+    RGBA { r: u8, g: u8, b: u8, a: u8 },
+    /// 0<=H<360, 0<=S<=1, 0<=L<=1. Hues of 360 or more wrap around.
     HSL { h: u16, s: f32, l: f32 },
     HSV { h: u16, s: f32, v: f32 },
+    /// Hue, whiteness, and blackness (CSS Color 4). 0<=H<360, 0<=W<=1, 0<=B<=1
+    ///
+    /// When W + B is more than 1 they are normalized so they add up to 1, making a gray.
+    HWB { h: u16, w: f32, b: f32 },
     CYMK { c: f32, y: f32, m: f32, k: f32 },
+    /// Perceptually uniform lightness, chroma, and hue (OKLCH). 0<=L<=1, 0<=C, 0<=H<360
+    ///
+    /// Equal steps of lightness or hue look like equal changes, which makes for smoother
+    /// gradients than HSL. Chroma is usually at most 0.4 and colors outside of sRGB are clamped.
+    Oklch { l: f32, c: f32, h: u16 },
 }
 
-impl Hash for Color {
-    fn hash<H: Hasher>(&self, state: &mut H) {
+/// What a color compares and hashes by, see [`Color`].
+#[derive(PartialEq, Eq, Hash)]
+enum ColorKey {
+    Default,
+    Palette(u8),
+    Rgb(u8, u8, u8),
+    Rgba(u8, u8, u8, u8),
+    /// The float variants by their variant and the RGB they render
+    Float(std::mem::Discriminant<Color>, (u8, u8, u8)),
+}
+
+impl Color {
+    fn key(&self) -> ColorKey {
+        if let Some(index) = self.palette_index() {
+            return ColorKey::Palette(index);
+        }
         match self {
-            Self::BLACK => "0".hash(state),
-            Self::RED => "1".hash(state),
-            Self::GREEN => "2".hash(state),
-            Self::YELLOW => "3".hash(state),
-            Self::BLUE => "4".hash(state),
-            Self::MAGENTA => "5".hash(state),
-            Self::CYAN => "6".hash(state),
-            Self::WHITE => "7".hash(state),
-            Color::Ansi(c) => c.hash(state),
-            Color::RGB { r, g, b } => {
-                r.hash(state);
-                g.hash(state);
-                b.hash(state);
-            }
-            Color::HSL { h, s, l } => {
-                h.hash(state);
-                s.to_bits().hash(state);
-                l.to_bits().hash(state);
-            }
-            Color::HSV { h, s, v } => {
-                h.hash(state);
-                s.to_bits().hash(state);
-                v.to_bits().hash(state);
-            }
-            Color::CYMK { c, y, m, k } => {
-                c.to_bits().hash(state);
-                y.to_bits().hash(state);
-                m.to_bits().hash(state);
-                k.to_bits().hash(state);
+            Color::RGB { r, g, b } => ColorKey::Rgb(*r, *g, *b),
+            Color::RGBA { r, g, b, a } => ColorKey::Rgba(*r, *g, *b, *a),
+            Color::HSL { .. } | Color::HSV { .. } | Color::HWB { .. } | Color::CYMK { .. } | Color::Oklch { .. } => {
+                ColorKey::Float(std::mem::discriminant(self), self.to_rgb())
             }
+            _ => ColorKey::Default,
         }
     }
 }
 
+impl PartialEq for Color {
+    fn eq(&self, other: &Self) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl Hash for Color {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.key().hash(state);
+    }
+}
+
+/// Writes the escape sequence: `{}` fg, `{:#}` bg, and `{:-}`/`{:-#}` their resets.
+///
+/// Width, fill, and alignment are ignored by design since padding an escape sequence doesn't
+/// pad anything visible.
 impl Display for Color {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        if f.sign_minus() {
-            write!(f, "\x1b[{}m", if f.alternate() { self.reset_bg() } else { self.reset_fg() })
-        } else {
-            write!(f, "\x1b[{}m", if f.alternate() { self.bg() } else { self.fg() })
+        match (f.sign_minus(), f.alternate()) {
+            (true, true) => write!(f, "\x1b[{}m", self.reset_bg()),
+            (true, false) => write!(f, "{}", self.reset_sequence()),
+            (false, true) => write!(f, "\x1b[{}m", self.bg()),
+            (false, false) => write!(f, "{}", self.sequence()),
         }
     }
 }
@@ -137,27 +348,173 @@ impl From<u8> for Color {
     }
 }
 
+/// Packed `0xRRGGBB` color where red is the third byte and blue is the lowest byte,
+/// Ex: `Color::from(0xff8800u32)` == `Color::RGB { r: 255, g: 136, b: 0 }`.
+///
+/// The high byte is ignored rather than read as alpha, so `0x00RRGGBB` constants stay opaque.
+/// Unsuffixed integer literals are ambiguous between this and `From<u8>`, so write `200u8` for
+/// an xterm color.
+impl From<u32> for Color {
+    fn from(value: u32) -> Self {
+        let [_, r, g, b] = value.to_be_bytes();
+        Color::RGB { r, g, b }
+    }
+}
+
+/// Values the single literal arm of [`color!`] turns into a color: an xterm index or a string.
+///
+/// This keeps `color!(214)` an xterm color without a `u8` suffix.
+#[doc(hidden)]
+pub trait LiteralColor {
+    fn into_color(self) -> Color;
+}
+
+impl LiteralColor for u8 {
+    fn into_color(self) -> Color {
+        Color::Ansi(self)
+    }
+}
+
+impl LiteralColor for &str {
+    fn into_color(self) -> Color {
+        Color::from(self)
+    }
+}
+
 impl From<String> for Color {
     fn from(value: String) -> Self {
         Color::from(value.as_str())
     }
 }
 
+/// Hex color, see [`Color::try_from_hex`].
+///
+/// # Panics
+/// If the string isn't a valid hex color.
 impl From<&str> for Color {
     fn from(value: &str) -> Self {
-        let mut value = value.strip_prefix("#").unwrap_or(value).to_string();
-        // If shorthand then first extend
-        if value.len() == 3 || value.len() == 4 {
-            value = value.chars().flat_map(|c| iter::repeat(c).take(2)).collect::<String>()
+        Color::try_from_hex(value).unwrap()
+    }
+}
+
+/// Error when a string can't be parsed into a [`Color`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ColorParseError {
+    /// The string that failed to parse
+    pub value: String,
+    pub reason: String,
+}
+
+impl ColorParseError {
+    fn new<S: Display>(value: &str, reason: S) -> Self {
+        ColorParseError { value: value.to_string(), reason: reason.to_string() }
+    }
+}
+
+impl Display for ColorParseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "invalid color '{}': {}", self.value, self.reason)
+    }
+}
+
+impl std::error::Error for ColorParseError {}
+
+/// Parse a hex color with an optional `#`, see [`Color::try_from_hex`].
+fn parse_hex(value: &str) -> Result<Color, ColorParseError> {
+    let hex = value.strip_prefix('#').unwrap_or(value);
+    if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(ColorParseError::new(value, "hex colors may only contain 0-9, a-f, and A-F"));
+    }
+    let hex = match hex.len() {
+        3 | 4 => hex.chars().flat_map(|c| iter::repeat_n(c, 2)).collect::<String>(),
+        6 | 8 => hex.to_string(),
+        _ => return Err(ColorParseError::new(value, "hex colors must have 3, 4, 6, or 8 digits")),
+    };
+    let channels = (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+        .collect::<Vec<_>>();
+    Ok(match channels[..] {
+        [r, g, b, a] => Color::RGBA { r, g, b, a },
+        _ => Color::RGB { r: channels[0], g: channels[1], b: channels[2] },
+    })
+}
+
+/// Parse a comma separated `r, g, b` triple where each channel is 0-255.
+fn parse_rgb_triple(value: &str, source: &str) -> Result<Color, ColorParseError> {
+    let channels = value.split(',').map(|c| c.trim()).collect::<Vec<_>>();
+    if channels.len() != 3 {
+        return Err(ColorParseError::new(source, format!("expected 3 channels but found {}", channels.len())));
+    }
+    let mut rgb = [0u8; 3];
+    for (channel, value) in rgb.iter_mut().zip(channels) {
+        *channel = value
+            .parse::<u8>()
+            .map_err(|_| ColorParseError::new(source, format!("'{}' is not a channel value from 0 to 255", value)))?;
+    }
+    Ok(Color::RGB { r: rgb[0], g: rgb[1], b: rgb[2] })
+}
+
+/// Non panicking parse of a color, Ex: `"#ff8800".parse::<Color>()`.
+///
+/// `TryFrom<&str>` isn't implemented since the panicking `From<&str>` already provides it.
+///
+/// Supported formats:
+/// - named: `red`, `green`, etc.
+/// - hex: `#rrggbb` | `#rgb` | `#rrggbbaa` | `#rgba`
+/// - rgb: `r, g, b` | `rgb(r, g, b)`
+/// - xterm: `0`-`255`
+/// - keywords: `transparent` is `RGBA` with an alpha of 0, `none` and `default` are
+///   `Color::Default` for explicitly not overriding the terminal's color
+impl FromStr for Color {
+    type Err = ColorParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let value = s.trim();
+        if value.starts_with('#') {
+            return parse_hex(value);
+        }
+        if let Some(inner) = value.strip_prefix("rgb(").and_then(|v| v.strip_suffix(')')) {
+            return parse_rgb_triple(inner, s);
+        }
+        if value.contains(',') {
+            return parse_rgb_triple(value, s);
+        }
+        if !value.is_empty() && value.chars().all(|c| c.is_ascii_digit()) {
+            return value
+                .parse::<u8>()
+                .map(Color::Ansi)
+                .map_err(|_| ColorParseError::new(s, "xterm colors must be from 0 to 255"));
+        }
+
+        match value.to_lowercase().as_str() {
+            "black" => Ok(Color::BLACK),
+            "red" => Ok(Color::RED),
+            "green" => Ok(Color::GREEN),
+            "yellow" => Ok(Color::YELLOW),
+            "blue" => Ok(Color::BLUE),
+            "magenta" => Ok(Color::MAGENTA),
+            "cyan" => Ok(Color::CYAN),
+            "white" => Ok(Color::WHITE),
+            "bright_black" => Ok(Color::BrightBlack),
+            "bright_red" => Ok(Color::BrightRed),
+            "bright_green" => Ok(Color::BrightGreen),
+            "bright_yellow" => Ok(Color::BrightYellow),
+            "bright_blue" => Ok(Color::BrightBlue),
+            "bright_magenta" => Ok(Color::BrightMagenta),
+            "bright_cyan" => Ok(Color::BrightCyan),
+            "bright_white" => Ok(Color::BrightWhite),
+            "default" | "none" => Ok(Color::Default),
+            "transparent" => Ok(Color::RGBA { r: 0, g: 0, b: 0, a: 0 }),
+            _ => Err(ColorParseError::new(s, "unknown color format")),
         }
-        let bytes: [u8; 4] = u32::from_str_radix(&value, 16).unwrap().to_be_bytes();
-        Color::RGB { r: bytes[1], g: bytes[2], b: bytes[3] }
     }
 }
 
 impl AnsiSequence for Color {
     fn ansi(&self) -> String {
         match self {
+            Self::Default => "9".to_string(),
             Self::BLACK => "0".to_string(),
             Self::RED => "1".to_string(),
             Self::GREEN => "2".to_string(),
@@ -166,32 +523,25 @@ impl AnsiSequence for Color {
             Self::MAGENTA => "5".to_string(),
             Self::CYAN => "6".to_string(),
             Self::WHITE => "7".to_string(),
+            // Same as the named color so equal colors render the same
+            Color::Ansi(value) if *value < 8 => value.to_string(),
+            // Only the 256 color form fits after the `3` or `4`, `fg` and `bg` use `9x` and `10x`
+            Self::BrightBlack
+            | Self::BrightRed
+            | Self::BrightGreen
+            | Self::BrightYellow
+            | Self::BrightBlue
+            | Self::BrightMagenta
+            | Self::BrightCyan
+            | Self::BrightWhite => format!("8;5;{}", self.palette_index().unwrap()),
             Color::Ansi(value) => format!("8;5;{}", value),
-            Color::RGB { r, g, b } => format!("8;2;{};{};{}", r, g, b),
-            Color::HSV { h, s, v } => {
-                // TODO: Convert from hsl to rgb
-                let c = v * s;
-                let h = *h as f32 / 60.0;
-                let x = c * (1.0 - ((h % 2.0) - 1.0).abs());
-                let m = v - c;
-
-                format_hs_color(c, h, x, m)
-            }
-            Color::HSL { h, s, l } => {
-                // chroma
-                let c = (1.0 - ((2.0 * l) - 1.0).abs()) * s;
-                let h = *h as f32 / 60.0;
-                let x = c * (1.0 - ((h % 2.0) - 1.0).abs());
-                let m = l - (c / 2.0);
-
-                format_hs_color(c, h, x, m)
-            }
-            Color::CYMK { c, y, m, k } => {
-                let kp = 1.0 - k;
-                let r = 255.0 * (1.0 - c) * kp;
-                let g = 255.0 * (1.0 - y) * kp;
-                let b = 255.0 * (1.0 - m) * kp;
-                format!("8;2;{};{};{}", r as u8, g as u8, b as u8)
+            // Nothing of a fully transparent color shows, so whatever is behind it is rendered
+            Color::RGBA { a: 0, .. } => "9".to_string(),
+            // Alpha is ignored, the terminal has no way to composite it
+            Color::RGB { .. } | Color::RGBA { .. } | Color::HSV { .. } | Color::HSL { .. } | Color::HWB { .. }
+            | Color::CYMK { .. } | Color::Oklch { .. } => {
+                let (r, g, b) = self.to_rgb();
+                format!("8;2;{};{};{}", r, g, b)
             }
         }
     }
@@ -199,9 +549,19 @@ impl AnsiSequence for Color {
     fn reset_ansi(&self) -> String {
         String::from("9")
     }
+
+    /// The color's ansi is only part of an sgr parameter, so the sequence is the foreground
+    /// color, same as `Display`.
+    fn sequence(&self) -> String {
+        format!("\x1b[{}m", self.fg())
+    }
+
+    fn reset_sequence(&self) -> String {
+        format!("\x1b[{}m", self.reset_fg())
+    }
 }
 
-fn format_hs_color(c: f32, h: f32, x: f32, m: f32) -> String {
+fn hs_rgb(c: f32, h: f32, x: f32, m: f32) -> (u8, u8, u8) {
     let (r, g, b) = match h {
         0.0..=1.0 => (c, x, 0.0),
         1.0..=2.0 => (x, c, 0.0),
@@ -214,81 +574,716 @@ fn format_hs_color(c: f32, h: f32, x: f32, m: f32) -> String {
         }
     };
 
-    format!(
-        "8;2;{};{};{}",
+    (
         ((r + m) * 255.0) as u8,
         ((g + m) * 255.0) as u8,
         ((b + m) * 255.0) as u8
     )
 }
 
+/// What an alpha color is composited over.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BlendBackground {
+    /// A known background color
+    Color(Color),
+    /// The terminal's default background which can't be known ahead of time.
+    TerminalDefault,
+}
+
+/// RGB values of the 16 system colors using the xterm defaults.
+const SYSTEM_COLORS: [(u8, u8, u8); 16] = [
+    (0, 0, 0),
+    (205, 0, 0),
+    (0, 205, 0),
+    (205, 205, 0),
+    (0, 0, 238),
+    (205, 0, 205),
+    (0, 205, 205),
+    (229, 229, 229),
+    (127, 127, 127),
+    (255, 0, 0),
+    (0, 255, 0),
+    (255, 255, 0),
+    (92, 92, 255),
+    (255, 0, 255),
+    (0, 255, 255),
+    (255, 255, 255),
+];
+
+/// The named colors in the order of their xterm index, the first 8 in the order of their sgr codes,
+/// `30`-`37` and `40`-`47`, and the bright colors in the order of `90`-`97` and `100`-`107`.
+const NAMED_COLORS: [Color; 16] = [
+    Color::BLACK,
+    Color::RED,
+    Color::GREEN,
+    Color::YELLOW,
+    Color::BLUE,
+    Color::MAGENTA,
+    Color::CYAN,
+    Color::WHITE,
+    Color::BrightBlack,
+    Color::BrightRed,
+    Color::BrightGreen,
+    Color::BrightYellow,
+    Color::BrightBlue,
+    Color::BrightMagenta,
+    Color::BrightCyan,
+    Color::BrightWhite,
+];
+
+/// Squared euclidean distance between two RGB colors.
+fn rgb_distance(a: (u8, u8, u8), b: (u8, u8, u8)) -> u32 {
+    let d = |x: u8, y: u8| (x as i32 - y as i32).pow(2) as u32;
+    d(a.0, b.0) + d(a.1, b.1) + d(a.2, b.2)
+}
+
+/// How the distance between two colors is measured when searching for the nearest color.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ColorDistance {
+    /// Euclidean distance of the rgb channels. Fast, but doesn't match how different colors
+    /// look, especially for saturated colors.
+    #[default]
+    Rgb,
+    /// Euclidean distance in the CIE Lab color space (deltaE 1976). Slower, but matches
+    /// perceived differences much more closely.
+    Lab,
+}
+
+impl ColorDistance {
+    fn between(&self, a: (u8, u8, u8), b: (u8, u8, u8)) -> f32 {
+        match self {
+            ColorDistance::Rgb => rgb_distance(a, b) as f32,
+            ColorDistance::Lab => {
+                let (a, b) = (lab(a), lab(b));
+                (a.0 - b.0).powi(2) + (a.1 - b.1).powi(2) + (a.2 - b.2).powi(2)
+            }
+        }
+    }
+}
+
+/// CIE Lab value of an sRGB color using the D65 white point.
+fn lab((r, g, b): (u8, u8, u8)) -> (f32, f32, f32) {
+    let (r, g, b) = (linear_rgb(r), linear_rgb(g), linear_rgb(b));
+    let x = (0.4124 * r + 0.3576 * g + 0.1805 * b) / 0.95047;
+    let y = 0.2126 * r + 0.7152 * g + 0.0722 * b;
+    let z = (0.0193 * r + 0.1192 * g + 0.9505 * b) / 1.08883;
+
+    let f = |t: f32| if t > 0.008856 { t.cbrt() } else { 7.787 * t + 16.0 / 116.0 };
+    let (fx, fy, fz) = (f(x), f(y), f(z));
+    (116.0 * fy - 16.0, 500.0 * (fx - fy), 200.0 * (fy - fz))
+}
+
+/// Linear value of an sRGB channel.
+fn linear_rgb(v: u8) -> f32 {
+    let v = v as f32 / 255.0;
+    if v <= 0.04045 { v / 12.92 } else { ((v + 0.055) / 1.055).powf(2.4) }
+}
+
+/// OKLab value of an sRGB color.
+fn oklab((r, g, b): (u8, u8, u8)) -> (f32, f32, f32) {
+    let (r, g, b) = (linear_rgb(r), linear_rgb(g), linear_rgb(b));
+    let l = (0.4122215 * r + 0.5363325 * g + 0.05144599 * b).cbrt();
+    let m = (0.2119035 * r + 0.6806995 * g + 0.107397 * b).cbrt();
+    let s = (0.08830246 * r + 0.2817188 * g + 0.6299787 * b).cbrt();
+    (
+        0.2104543 * l + 0.7936178 * m - 0.004072047 * s,
+        1.977998 * l - 2.428592 * m + 0.4505937 * s,
+        0.02590404 * l + 0.7827718 * m - 0.8086758 * s,
+    )
+}
+
+/// sRGB value of an OKLab color, clamping colors outside of sRGB.
+fn oklab_rgb((l, a, b): (f32, f32, f32)) -> (u8, u8, u8) {
+    let cube = |v: f32| v * v * v;
+    let lc = cube(l + 0.3963378 * a + 0.2158038 * b);
+    let mc = cube(l - 0.1055613 * a - 0.06385417 * b);
+    let sc = cube(l - 0.08948418 * a - 1.291486 * b);
+    (
+        srgb(4.076742 * lc - 3.307712 * mc + 0.2309699 * sc),
+        srgb(-1.268438 * lc + 2.609757 * mc - 0.3413194 * sc),
+        srgb(-0.004196086 * lc - 0.7034186 * mc + 1.707615 * sc),
+    )
+}
+
+/// sRGB channel of a linear value, clamped to 0-255.
+fn srgb(v: f32) -> u8 {
+    let v = if v <= 0.0031308 { 12.92 * v } else { 1.055 * v.powf(1.0 / 2.4) - 0.055 };
+    (v.clamp(0.0, 1.0) * 255.0).round() as u8
+}
+
+/// RGB value of an xterm 256 color index.
+///
+/// 0-15 are the system colors, 16-231 are the 6x6x6 color cube, and 232-255 are the grayscale ramp.
+fn xterm_rgb(index: u8) -> (u8, u8, u8) {
+    match index {
+        0..=15 => SYSTEM_COLORS[index as usize],
+        16..=231 => {
+            let level = |v: u8| if v == 0 { 0 } else { 55 + v * 40 };
+            let i = index - 16;
+            (level(i / 36), level((i / 6) % 6), level(i % 6))
+        }
+        _ => {
+            let v = 8 + (index - 232) * 10;
+            (v, v, v)
+        }
+    }
+}
+
 impl Color {
-    pub fn fg(&self) -> String {
-        format!("3{}", self.ansi())
+    /// Xterm palette index of the named and xterm colors.
+    fn palette_index(&self) -> Option<u8> {
+        match self {
+            Color::Ansi(index) => Some(*index),
+            _ => NAMED_COLORS
+                .iter()
+                .position(|named| std::mem::discriminant(named) == std::mem::discriminant(self))
+                .map(|index| index as u8),
+        }
     }
-    pub fn bg(&self) -> String {
-        format!("4{}", self.ansi())
+
+    /// Parse a hex color with an optional `#`: `rgb`, `rrggbb`, or with alpha `rgba` and
+    /// `rrggbbaa` which are parsed as [`Color::RGBA`].
+    ///
+    /// Digits are case insensitive and shorthand digits keep their case when expanded, so
+    /// `#AbC` == `#aabbcc`.
+    pub fn try_from_hex(value: &str) -> Result<Color, ColorParseError> {
+        parse_hex(value)
     }
-    pub fn reset_fg(&self) -> String {
-        format!("3{}", self.reset_ansi())
+
+    /// The color as its red, green, and blue components.
+    ///
+    /// Named colors are their xterm default 4-bit palette value, `RED` == `205, 0, 0`, and alpha is
+    /// dropped. The terminal default color can't be known so it is treated as black.
+    pub fn to_rgb(&self) -> (u8, u8, u8) {
+        match self {
+            Self::Default => (0, 0, 0),
+            Self::BLACK => SYSTEM_COLORS[0],
+            Self::RED => SYSTEM_COLORS[1],
+            Self::GREEN => SYSTEM_COLORS[2],
+            Self::YELLOW => SYSTEM_COLORS[3],
+            Self::BLUE => SYSTEM_COLORS[4],
+            Self::MAGENTA => SYSTEM_COLORS[5],
+            Self::CYAN => SYSTEM_COLORS[6],
+            Self::WHITE => SYSTEM_COLORS[7],
+            Self::BrightBlack
+            | Self::BrightRed
+            | Self::BrightGreen
+            | Self::BrightYellow
+            | Self::BrightBlue
+            | Self::BrightMagenta
+            | Self::BrightCyan
+            | Self::BrightWhite => SYSTEM_COLORS[self.palette_index().unwrap() as usize],
+            Color::Ansi(value) => xterm_rgb(*value),
+            Color::RGB { r, g, b } | Color::RGBA { r, g, b, .. } => (*r, *g, *b),
+            Color::HSV { h, s, v } => {
+                let c = v * s;
+                let h = (*h % 360) as f32 / 60.0;
+                let x = c * (1.0 - ((h % 2.0) - 1.0).abs());
+                hs_rgb(c, h, x, v - c)
+            }
+            Color::HSL { h, s, l } => {
+                let c = (1.0 - ((2.0 * l) - 1.0).abs()) * s;
+                let h = (*h % 360) as f32 / 60.0;
+                let x = c * (1.0 - ((h % 2.0) - 1.0).abs());
+                hs_rgb(c, h, x, l - (c / 2.0))
+            }
+            Color::HWB { h, w, b } => {
+                let (w, b) = if w + b > 1.0 { (w / (w + b), b / (w + b)) } else { (*w, *b) };
+                let (r, g, bl) = Color::HSL { h: *h, s: 1.0, l: 0.5 }.to_rgb();
+                let tint = |v: u8| (v as f32 * (1.0 - w - b) + w * 255.0).round() as u8;
+                (tint(r), tint(g), tint(bl))
+            }
+            Color::CYMK { c, y, m, k } => {
+                let kp = 1.0 - k;
+                (
+                    (255.0 * (1.0 - c) * kp) as u8,
+                    (255.0 * (1.0 - m) * kp) as u8,
+                    (255.0 * (1.0 - y) * kp) as u8,
+                )
+            }
+            Color::Oklch { l, c, h } => {
+                let h = (*h as f32).to_radians();
+                oklab_rgb((*l, c * h.cos(), c * h.sin()))
+            }
+        }
     }
-    pub fn reset_bg(&self) -> String {
-        format!("4{}", self.reset_ansi())
+
+    /// The color converted to [`Color::Oklch`] from its [`Color::to_rgb`] value.
+    pub fn to_oklch(&self) -> Color {
+        if let Color::Oklch { .. } = self {
+            return *self;
+        }
+        let (l, a, b) = oklab(self.to_rgb());
+        let c = (a * a + b * b).sqrt();
+        // Grays have no hue
+        let h = if c < 1e-4 { 0 } else { (b.atan2(a).to_degrees().rem_euclid(360.0).round() as u16) % 360 };
+        Color::Oklch { l, c, h }
     }
 
-    pub fn rgb(r: u8, g: u8, b: u8) -> Self {
-        Self::RGB { r, g, b }
+    /// Red channel of [`Color::to_rgb`].
+    pub fn red(&self) -> u8 {
+        self.to_rgb().0
     }
 
-    pub fn hex(hex: String) -> Self {
-        Self::from(hex)
+    /// Green channel of [`Color::to_rgb`].
+    pub fn green(&self) -> u8 {
+        self.to_rgb().1
     }
 
-    pub fn hsl(h: u16, s: f32, l: f32) -> Result<Self, String> {
-        if s > 1.0 || l > 1.0 {
-            return Err("Saturation or lightness in hsl is greater than to 1.0 which is out of bounds".into());
-        }
-        if s < 0.0 || l < 0.0 {
-            return Err("Saturation or lightness in hsl is less than 0.0 which is out of bounds".into());
-        }
-        if h >= 360 {
-            return Err("Hue in hsl is greater than or equal to 360 which is out of bounds".into());
+    /// Blue channel of [`Color::to_rgb`].
+    pub fn blue(&self) -> u8 {
+        self.to_rgb().2
+    }
+
+    /// The color as `RGB` with the red channel replaced.
+    pub fn with_red(&self, red: u8) -> Color {
+        let (_, g, b) = self.to_rgb();
+        Color::RGB { r: red, g, b }
+    }
+
+    /// The color as `RGB` with the green channel replaced.
+    pub fn with_green(&self, green: u8) -> Color {
+        let (r, _, b) = self.to_rgb();
+        Color::RGB { r, g: green, b }
+    }
+
+    /// The color as `RGB` with the blue channel replaced.
+    pub fn with_blue(&self, blue: u8) -> Color {
+        let (r, g, _) = self.to_rgb();
+        Color::RGB { r, g, b: blue }
+    }
+
+    /// Alpha channel of the color, 255 for every color except `RGBA`.
+    ///
+    /// Terminals ignore alpha, it is kept for callers doing their own blending.
+    pub fn alpha(&self) -> u8 {
+        match self {
+            Color::RGBA { a, .. } => *a,
+            _ => 255,
         }
-        Ok(Self::HSL { h, s, l })
     }
 
-    pub fn hsv(h: u16, s: f32, v: f32) -> Result<Self, String> {
-        if s > 1.0 || v > 1.0 {
-            return Err("Saturation or Value in hsv is greater than to 1.0 which is out of bounds".into());
+    /// Composite an `RGBA` color over the background resulting in an opaque `RGB` color.
+    /// All other colors are already opaque and are returned as is.
+    ///
+    /// When the background is `TerminalDefault` the straight RGB value is used. Guessing a
+    /// background, like black, would give the wrong result on any terminal with a different
+    /// theme, so the translucent color is instead rendered as fully opaque. A fully transparent
+    /// color is `Color::Default` over the terminal default.
+    pub fn composite(&self, background: BlendBackground) -> Color {
+        match (self, background) {
+            (Color::RGBA { a: 0, .. }, BlendBackground::TerminalDefault | BlendBackground::Color(Color::Default)) => {
+                Color::Default
+            }
+            (Color::RGBA { r, g, b, .. }, BlendBackground::TerminalDefault | BlendBackground::Color(Color::Default)) => {
+                Color::RGB { r: *r, g: *g, b: *b }
+            }
+            (Color::RGBA { r, g, b, a }, BlendBackground::Color(bg)) => {
+                let (br, bg, bb) = bg.composite(BlendBackground::TerminalDefault).to_rgb();
+                let alpha = *a as f32 / 255.0;
+                let mix = |f: u8, b: u8| (f as f32 * alpha + b as f32 * (1.0 - alpha)).round() as u8;
+                Color::RGB { r: mix(*r, br), g: mix(*g, bg), b: mix(*b, bb) }
+            }
+            _ => *self,
         }
-        if s < 0.0 || v < 0.0 {
-            return Err("Saturation or Value in hsv is less than 0.0 which is out of bounds".into());
+    }
+
+    /// The color as `RGBA` with the given alpha, keeping the [`Color::to_rgb`] value so
+    /// `c.with_alpha(255).to_rgb() == c.to_rgb()`.
+    pub fn with_alpha(&self, alpha: u8) -> Color {
+        let (r, g, b) = self.to_rgb();
+        Color::RGBA { r, g, b, a: alpha }
+    }
+
+    /// Nearest xterm 256 color as `Color::Ansi`.
+    ///
+    /// The color cube and grayscale ramp are searched before the system colors since the system
+    /// colors are often changed by terminal themes. A system color is only used if it's closer.
+    pub fn to_ansi256(&self) -> Color {
+        self.to_ansi256_by(ColorDistance::Rgb)
+    }
+
+    /// Same as [`Color::to_ansi256`] but the nearest color is found with the given distance.
+    pub fn to_ansi256_by(&self, distance: ColorDistance) -> Color {
+        if *self == Color::Default {
+            return *self;
         }
-        if h >= 360 {
-            return Err("Hue in hsv is greater than or equal to 360 which is out of bounds".into());
+        let rgb = self.to_rgb();
+        let index = (16..=255u8)
+            .chain(0..16)
+            .min_by(|a, b| distance.between(rgb, xterm_rgb(*a)).total_cmp(&distance.between(rgb, xterm_rgb(*b))))
+            .unwrap();
+        Color::Ansi(index)
+    }
+
+    /// Nearest of the 16 system colors as one of the named colors, Ex: `Color::BrightRed`.
+    pub fn to_ansi16(&self) -> Color {
+        self.to_ansi16_by(ColorDistance::Rgb)
+    }
+
+    /// Same as [`Color::to_ansi16`] but the nearest color is found with the given distance.
+    pub fn to_ansi16_by(&self, distance: ColorDistance) -> Color {
+        if *self == Color::Default {
+            return *self;
         }
-        Ok(Self::HSV { h, s, v })
+        let rgb = self.to_rgb();
+        let index = (0..16u8)
+            .min_by(|a, b| {
+                distance
+                    .between(rgb, SYSTEM_COLORS[*a as usize])
+                    .total_cmp(&distance.between(rgb, SYSTEM_COLORS[*b as usize]))
+            })
+            .unwrap();
+        NAMED_COLORS[index as usize]
     }
 
-    pub fn cymk(c: f32, y: f32, m: f32, k: f32) -> Result<Self, String> {
-        if c < 0.0 || y < 0.0 || m < 0.0 || k < 0.0 {
-            return Err("CYMK value is less than 0.0 which is out of bounds".into());
+    /// Nearest color that can be rendered with the color support using the RGB distance: an
+    /// xterm 256 color for `EightBit`, one of the 16 system colors for `Standard`, and
+    /// `Color::Default` for `None`. Colors that are already supported are returned as is.
+    ///
+    /// Rendering with [`Style::transition_for`] downgrades the colors automatically.
+    pub fn downgrade(&self, support: ColorSupport) -> Color {
+        self.downsample(support).unwrap_or(Color::Default)
+    }
+
+    /// Nearest color that can be rendered with the color support. `None` when colors aren't
+    /// supported.
+    pub(crate) fn downsample(&self, support: ColorSupport) -> Option<Color> {
+        if support.supports(self) {
+            return Some(*self);
         }
-        if c > 1.0 || y > 1.0 || m > 1.0 || k > 1.0 {
-            return Err("CYMK value is greater than 1.0 which is out of bounds".into());
+        match support {
+            ColorSupport::None => None,
+            ColorSupport::Standard => Some(self.to_ansi16()),
+            ColorSupport::EightBit | ColorSupport::TrueColor => Some(self.to_ansi256()),
         }
-        Ok(Self::CYMK { c, y, m, k })
     }
-}
 
-/// Shorthand macro for system colors, rgb, hex, hsl, hsv, xterm, and cymk colors.
-///
-/// Supported formats:
-/// - hsl: (h, s%, l%) | (h s% l%)
-/// - hsv: (h, s%, v%) | (h s% v%)
+    /// Hue, chroma, max, and min of the color's rgb channels normalized to 0.0-1.0
+    fn hue_components(&self) -> (u16, f32, f32, f32) {
+        let (r, g, b) = self.to_rgb();
+        let (r, g, b) = (r as f32 / 255.0, g as f32 / 255.0, b as f32 / 255.0);
+        let max = r.max(g).max(b);
+        let min = r.min(g).min(b);
+        let chroma = max - min;
+
+        // Hue is undefined for achromatic colors
+        let hue = if chroma == 0.0 {
+            0.0
+        } else if max == r {
+            60.0 * (((g - b) / chroma).rem_euclid(6.0))
+        } else if max == g {
+            60.0 * (((b - r) / chroma) + 2.0)
+        } else {
+            60.0 * (((r - g) / chroma) + 4.0)
+        };
+        ((hue.round() as u16) % 360, chroma, max, min)
+    }
+
+    /// Hue (0-359), saturation (0.0-1.0), and lightness (0.0-1.0) of the color.
+    ///
+    /// Achromatic colors have a hue of 0.
+    pub fn to_hsl(&self) -> (u16, f32, f32) {
+        let (h, chroma, max, min) = self.hue_components();
+        let l = (max + min) / 2.0;
+        let s = if l == 0.0 || l == 1.0 { 0.0 } else { chroma / (1.0 - (2.0 * l - 1.0).abs()) };
+        (h, s, l)
+    }
+
+    /// Whether the color is a gray, its RGB channels being within 3 of each other, Ex: to pick the
+    /// grayscale ramp when downsampling.
+    ///
+    /// Named and xterm colors are gray when their palette color is, which includes the grayscale
+    /// ramp, 232-255, and the black and white system colors. `Default` is never gray since its
+    /// color isn't known.
+    pub fn is_grayscale(&self) -> bool {
+        if *self == Color::Default {
+            return false;
+        }
+        let (r, g, b) = self.to_rgb();
+        r.max(g).max(b) - r.min(g).min(b) <= 3
+    }
+
+    /// Hue (0-359), saturation (0.0-1.0), and value (0.0-1.0) of the color.
+    ///
+    /// Achromatic colors have a hue of 0.
+    pub fn to_hsv(&self) -> (u16, f32, f32) {
+        let (h, chroma, max, _) = self.hue_components();
+        let s = if max == 0.0 { 0.0 } else { chroma / max };
+        (h, s, max)
+    }
+
+    /// Textual form of the color that parses back into the same color with `FromStr`.
+    ///
+    /// Named colors and `Default` are their lowercase name, xterm colors are their index, `RGBA`
+    /// colors are `#rrggbbaa`, and every other color is `#rrggbb`.
+    pub fn name_or_hex(&self) -> String {
+        match self {
+            Self::Default => "default".to_string(),
+            Self::BLACK => "black".to_string(),
+            Self::RED => "red".to_string(),
+            Self::GREEN => "green".to_string(),
+            Self::YELLOW => "yellow".to_string(),
+            Self::BLUE => "blue".to_string(),
+            Self::MAGENTA => "magenta".to_string(),
+            Self::CYAN => "cyan".to_string(),
+            Self::WHITE => "white".to_string(),
+            Self::BrightBlack => "bright_black".to_string(),
+            Self::BrightRed => "bright_red".to_string(),
+            Self::BrightGreen => "bright_green".to_string(),
+            Self::BrightYellow => "bright_yellow".to_string(),
+            Self::BrightBlue => "bright_blue".to_string(),
+            Self::BrightMagenta => "bright_magenta".to_string(),
+            Self::BrightCyan => "bright_cyan".to_string(),
+            Self::BrightWhite => "bright_white".to_string(),
+            Color::Ansi(value) => value.to_string(),
+            Color::RGBA { r, g, b, a } => format!("#{:02x}{:02x}{:02x}{:02x}", r, g, b, a),
+            _ => {
+                let (r, g, b) = self.to_rgb();
+                format!("#{:02x}{:02x}{:02x}", r, g, b)
+            }
+        }
+    }
+
+    /// Snap each channel to the nearest web safe value (0, 51, 102, 153, 204, 255).
+    pub fn to_websafe(&self) -> Color {
+        if *self == Color::Default {
+            return *self;
+        }
+        let snap = |v: u8| ((v as u16 + 25) / 51 * 51) as u8;
+        let (r, g, b) = self.to_rgb();
+        Color::RGB { r: snap(r), g: snap(g), b: snap(b) }
+    }
+
+    /// Same as [`Color::to_websafe`] but the nearest color is found with the given distance.
+    pub fn to_websafe_by(&self, distance: ColorDistance) -> Color {
+        match distance {
+            ColorDistance::Rgb => self.to_websafe(),
+            ColorDistance::Lab => {
+                if *self == Color::Default {
+                    return *self;
+                }
+                let rgb = self.to_rgb();
+                let (r, g, b) = (0..216u32)
+                    .map(|i| ((i / 36 * 51) as u8, (i / 6 % 6 * 51) as u8, (i % 6 * 51) as u8))
+                    .min_by(|a, b| distance.between(rgb, *a).total_cmp(&distance.between(rgb, *b)))
+                    .unwrap();
+                Color::RGB { r, g, b }
+            }
+        }
+    }
+
+    pub fn fg(&self) -> String {
+        match self.palette_index() {
+            Some(index @ 8..=15) => format!("9{}", index - 8),
+            _ => format!("3{}", self.ansi()),
+        }
+    }
+    pub fn bg(&self) -> String {
+        match self.palette_index() {
+            Some(index @ 8..=15) => format!("10{}", index - 8),
+            _ => format!("4{}", self.ansi()),
+        }
+    }
+    pub fn reset_fg(&self) -> String {
+        format!("3{}", self.reset_ansi())
+    }
+    pub fn reset_bg(&self) -> String {
+        format!("4{}", self.reset_ansi())
+    }
+
+    pub fn rgb(r: u8, g: u8, b: u8) -> Self {
+        Self::RGB { r, g, b }
+    }
+
+    /// RGB color from normalized floats (0.0-1.0). Values outside the range are clamped.
+    pub fn rgb_f(r: f32, g: f32, b: f32) -> Self {
+        let scale = |v: f32| (v.clamp(0.0, 1.0) * 255.0).round() as u8;
+        Self::RGB { r: scale(r), g: scale(g), b: scale(b) }
+    }
+
+    /// Mix this color with the other color by `t`, where `0.0` is this color and `1.0` is the
+    /// other color. Each channel of their [`Color::to_rgb`] values is interpolated into an `RGB`.
+    ///
+    /// `t` is clamped to `0.0..=1.0` and NaN is treated as `0.0`. At the ends the colors are
+    /// returned as is.
+    pub fn blend(&self, other: &Color, t: f32) -> Color {
+        self.blend_by(other, t, |a, b, t| (a as f32 + (b as f32 - a as f32) * t).round() as u8)
+    }
+
+    /// Same as [`Color::blend`] but the sRGB channels are decoded and mixed in linear light,
+    /// which keeps the middle of a fade from looking darker than either end.
+    pub fn blend_srgb(&self, other: &Color, t: f32) -> Color {
+        self.blend_by(other, t, |a, b, t| {
+            let (a, b) = (linear_rgb(a), linear_rgb(b));
+            srgb(a + (b - a) * t)
+        })
+    }
+
+    fn blend_by<F: Fn(u8, u8, f32) -> u8>(&self, other: &Color, t: f32, mix: F) -> Color {
+        let t = if t.is_nan() { 0.0 } else { t.clamp(0.0, 1.0) };
+        if t == 0.0 {
+            return *self;
+        }
+        if t == 1.0 {
+            return *other;
+        }
+        let ((r1, g1, b1), (r2, g2, b2)) = (self.to_rgb(), other.to_rgb());
+        Color::RGB { r: mix(r1, r2, t), g: mix(g1, g2, t), b: mix(b1, b2, t) }
+    }
+
+    /// `steps` evenly spaced colors from this color to the other color, including both, made
+    /// with [`Color::blend`]. One step is just this color and zero steps yields nothing.
+    ///
+    /// Ex: `Color::gradient(&from, &to, bar_width)` colors each cell of a progress bar.
+    pub fn gradient(&self, to: &Color, steps: usize) -> impl Iterator<Item = Color> {
+        let (from, to) = (*self, *to);
+        (0..steps).map(move |step| {
+            let t = if steps > 1 { step as f32 / (steps - 1) as f32 } else { 0.0 };
+            from.blend(&to, t)
+        })
+    }
+
+    /// `count` `HSL` colors with the hue and saturation of this color from dark to light, Ex: a
+    /// tonal palette from one accent color.
+    ///
+    /// The lightness goes from 0.1 to 0.9 so the ends don't become pure black and white, use
+    /// [`Color::shades_between`] for other ends.
+    pub fn shades(&self, count: usize) -> Vec<Color> {
+        self.shades_between(count, 0.1, 0.9)
+    }
+
+    /// Same as [`Color::shades`] with the lightness going from `min` to `max`, each clamped to
+    /// 0.0-1.0. A single shade is halfway between them.
+    pub fn shades_between(&self, count: usize, min: f32, max: f32) -> Vec<Color> {
+        let (h, s, _) = self.to_hsl();
+        let (min, max) = (min.clamp(0.0, 1.0), max.clamp(0.0, 1.0));
+        (0..count)
+            .map(|step| {
+                let t = if count > 1 { step as f32 / (count - 1) as f32 } else { 0.5 };
+                Color::HSL { h, s, l: min + (max - min) * t }
+            })
+            .collect()
+    }
+
+    /// `steps` evenly spaced `RGB` colors along color stops, like a CSS gradient.
+    ///
+    /// Each stop is a position from 0.0 to 1.0 and a color. Positions outside the range are
+    /// clamped, and the stops are sorted by position. Colors before the first stop and after the
+    /// last stop are the color of that stop; between two stops the channels are interpolated.
+    pub fn multi_gradient(stops: &[(f32, Color)], steps: usize) -> Vec<Color> {
+        if stops.is_empty() {
+            return Vec::new();
+        }
+        let mut stops = stops
+            .iter()
+            .map(|(position, color)| (position.clamp(0.0, 1.0), color.to_rgb()))
+            .collect::<Vec<_>>();
+        stops.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        let lerp = |a: u8, b: u8, t: f32| (a as f32 + (b as f32 - a as f32) * t).round() as u8;
+        (0..steps)
+            .map(|step| {
+                let t = if steps > 1 { step as f32 / (steps - 1) as f32 } else { 0.0 };
+                let next = stops.iter().position(|(position, _)| *position >= t);
+                let (r, g, b) = match next {
+                    None => stops[stops.len() - 1].1,
+                    Some(0) => stops[0].1,
+                    Some(i) => {
+                        let ((p0, c0), (p1, c1)) = (stops[i - 1], stops[i]);
+                        let t = if p1 > p0 { (t - p0) / (p1 - p0) } else { 1.0 };
+                        (lerp(c0.0, c1.0, t), lerp(c0.1, c1.1, t), lerp(c0.2, c1.2, t))
+                    }
+                };
+                Color::RGB { r, g, b }
+            })
+            .collect()
+    }
+
+    pub fn hex(hex: String) -> Self {
+        Self::from(hex)
+    }
+
+    pub fn hsl(h: u16, s: f32, l: f32) -> Result<Self, String> {
+        if s > 1.0 || l > 1.0 {
+            return Err("Saturation or lightness in hsl is greater than to 1.0 which is out of bounds".into());
+        }
+        if s < 0.0 || l < 0.0 {
+            return Err("Saturation or lightness in hsl is less than 0.0 which is out of bounds".into());
+        }
+        if h >= 360 {
+            return Err("Hue in hsl is greater than or equal to 360 which is out of bounds".into());
+        }
+        Ok(Self::HSL { h, s, l })
+    }
+
+    pub fn hsv(h: u16, s: f32, v: f32) -> Result<Self, String> {
+        if s > 1.0 || v > 1.0 {
+            return Err("Saturation or Value in hsv is greater than to 1.0 which is out of bounds".into());
+        }
+        if s < 0.0 || v < 0.0 {
+            return Err("Saturation or Value in hsv is less than 0.0 which is out of bounds".into());
+        }
+        if h >= 360 {
+            return Err("Hue in hsv is greater than or equal to 360 which is out of bounds".into());
+        }
+        Ok(Self::HSV { h, s, v })
+    }
+
+    /// Perceptually uniform lightness, chroma, and hue, see [`Color::Oklch`].
+    pub fn oklch(l: f32, c: f32, h: u16) -> Result<Self, String> {
+        if !(0.0..=1.0).contains(&l) {
+            return Err("Lightness in oklch is outside of 0.0 to 1.0 which is out of bounds".into());
+        }
+        if c < 0.0 {
+            return Err("Chroma in oklch is less than 0.0 which is out of bounds".into());
+        }
+        if h >= 360 {
+            return Err("Hue in oklch is greater than or equal to 360 which is out of bounds".into());
+        }
+        Ok(Self::Oklch { l, c, h })
+    }
+
+    /// Hue, whiteness, and blackness. A whiteness and blackness adding up to more than 1.0 are
+    /// normalized so they add up to 1.0.
+    pub fn hwb(h: u16, w: f32, b: f32) -> Result<Self, String> {
+        if w > 1.0 || b > 1.0 {
+            return Err("Whiteness or blackness in hwb is greater than 1.0 which is out of bounds".into());
+        }
+        if w < 0.0 || b < 0.0 {
+            return Err("Whiteness or blackness in hwb is less than 0.0 which is out of bounds".into());
+        }
+        if h >= 360 {
+            return Err("Hue in hwb is greater than or equal to 360 which is out of bounds".into());
+        }
+        if w + b > 1.0 {
+            return Ok(Self::HWB { h, w: w / (w + b), b: b / (w + b) });
+        }
+        Ok(Self::HWB { h, w, b })
+    }
+
+    pub fn cymk(c: f32, y: f32, m: f32, k: f32) -> Result<Self, String> {
+        if c < 0.0 || y < 0.0 || m < 0.0 || k < 0.0 {
+            return Err("CYMK value is less than 0.0 which is out of bounds".into());
+        }
+        if c > 1.0 || y > 1.0 || m > 1.0 || k > 1.0 {
+            return Err("CYMK value is greater than 1.0 which is out of bounds".into());
+        }
+        Ok(Self::CYMK { c, y, m, k })
+    }
+}
+
+/// Shorthand macro for system colors, rgb, hex, hsl, hsv, hwb, oklch, xterm, and cymk colors.
+///
+/// Supported formats:
+/// - hsl: (h, s%, l%) | (h s% l%)
+/// - hsv: (h, s%, v%) | (h s% v%)
+/// - hwb: (h, w%, b%) | (h w% b%)
+/// - oklch: (oklch l%, c, h) | (oklch l% c h)
 /// - rgb: (r, g, b) | (r g b)
 /// - hex: #rrggbb | #rgb
 /// - xterm: 0-255
+/// - named with alpha: (name / a%) | (name, a%)
 #[macro_export]
 macro_rules! _color {
         (hsl $h: literal, $s: literal%, $l: literal%) => {
@@ -303,6 +1298,18 @@ macro_rules! _color {
         (hsv $h: literal $s: literal% $v: literal%) => {
             $crate::style::Color::hsv($h, $s as f32/100.0, $v as f32/100.0).unwrap()
         };
+        (hwb $h: literal, $w: literal%, $b: literal%) => {
+            $crate::style::Color::hwb($h, $w as f32/100.0, $b as f32/100.0).unwrap()
+        };
+        (hwb $h: literal $w: literal% $b: literal%) => {
+            $crate::style::Color::hwb($h, $w as f32/100.0, $b as f32/100.0).unwrap()
+        };
+        (oklch $l: literal%, $c: literal, $h: literal) => {
+            $crate::style::Color::oklch($l as f32/100.0, $c as f32, $h).unwrap()
+        };
+        (oklch $l: literal% $c: literal $h: literal) => {
+            $crate::style::Color::oklch($l as f32/100.0, $c as f32, $h).unwrap()
+        };
         ($c: literal%, $y: literal%, $m: literal%, $k: literal%) => {
             $crate::style::Color::cymk($c as f32/100.0, $y as f32/100.0, $m as f32/100.0, $k as f32/100.0).unwrap()
         };
@@ -316,7 +1323,7 @@ macro_rules! _color {
             $crate::style::Color::RGB { r: $r, g: $g, b: $b }
         };
         ($ansi: literal) => {
-            $crate::style::Color::from($ansi)
+            $crate::style::LiteralColor::into_color($ansi)
         };
         (#$hex: literal) => {
             $crate::style::Color::from($($hex)*)
@@ -324,6 +1331,12 @@ macro_rules! _color {
         (#$($hex: tt)*) => {
             $crate::style::Color::from(stringify!($($hex)*))
         };
+        ($color: ident / $a: literal%) => {
+            paste::paste!($crate::style::Color::[<$color:upper>]).with_alpha(($a as f32 / 100.0 * 255.0).clamp(0.0, 255.0).round() as u8)
+        };
+        ($color: ident, $a: literal%) => {
+            paste::paste!($crate::style::Color::[<$color:upper>]).with_alpha(($a as f32 / 100.0 * 255.0).clamp(0.0, 255.0).round() as u8)
+        };
         ($color: ident) => {
             paste::paste!($crate::style::Color::[<$color:upper>])
         };
@@ -338,15 +1351,144 @@ impl Display for _Placeholder {
     }
 }
 
-#[derive(PartialEq, Default, Hash, Clone, Debug)]
+#[derive(Default, Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Style {
     pub flags: StyleFlag,
     pub fg: Option<Color>,
     pub bg: Option<Color>,
     pub link: Option<Hyperlink>,
+    /// Name for debugging and theming. It is ignored when rendering, comparing, and hashing so
+    /// styles with the same visual content are the same style regardless of name.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub name: Option<&'static str>,
+}
+
+impl PartialEq for Style {
+    fn eq(&self, other: &Self) -> bool {
+        self.flags == other.flags && self.fg == other.fg && self.bg == other.bg && self.link == other.link
+    }
+}
+
+impl Hash for Style {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.flags.hash(state);
+        self.fg.hash(state);
+        self.bg.hash(state);
+        self.link.hash(state);
+    }
+}
+
+/// Error when a string can't be parsed into a [`Style`] with [`Style::parse_ansi`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StyleParseError {
+    /// The string that failed to parse
+    pub value: String,
+    pub reason: String,
+}
+
+impl StyleParseError {
+    fn new<S: Display>(value: &str, reason: S) -> Self {
+        StyleParseError { value: value.to_string(), reason: reason.to_string() }
+    }
+}
+
+impl Display for StyleParseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "invalid style sequence '{}': {}", self.value.escape_debug(), self.reason)
+    }
+}
+
+impl std::error::Error for StyleParseError {}
+
+/// Parse the color after a `38` or `48`, either `5;n` or `2;r;g;b`.
+fn parse_sgr_color(codes: &mut impl Iterator<Item = u16>, source: &str) -> Result<Color, StyleParseError> {
+    let channel = |codes: &mut dyn Iterator<Item = u16>| {
+        codes
+            .next()
+            .and_then(|c| u8::try_from(c).ok())
+            .ok_or_else(|| StyleParseError::new(source, "expected a color channel from 0 to 255"))
+    };
+    match codes.next() {
+        Some(5) => Ok(Color::Ansi(channel(codes)?)),
+        Some(2) => Ok(Color::RGB { r: channel(codes)?, g: channel(codes)?, b: channel(codes)? }),
+        _ => Err(StyleParseError::new(source, "expected `5;n` or `2;r;g;b` after 38 or 48")),
+    }
 }
 
 impl Style {
+    /// Parse the style back from its escape sequence, the inverse of [`AnsiSequence::sequence`],
+    /// Ex: `\x1b[1;3;38;2;255;0;0m` is bold, italic, and `Color::RGB { r: 255, g: 0, b: 0 }`.
+    ///
+    /// Multiple sgr sequences are applied in order and a hyperlink sets the link. Unknown codes
+    /// are ignored, use [`Style::parse_ansi_strict`] to error on them instead.
+    pub fn parse_ansi(s: &str) -> Result<Style, StyleParseError> {
+        Style::parse_ansi_with(s, false)
+    }
+
+    /// Same as [`Style::parse_ansi`] except unknown sgr codes are an error listing the codes.
+    pub fn parse_ansi_strict(s: &str) -> Result<Style, StyleParseError> {
+        Style::parse_ansi_with(s, true)
+    }
+
+    fn parse_ansi_with(s: &str, strict: bool) -> Result<Style, StyleParseError> {
+        let mut style = Style::default();
+        let mut unknown = Vec::new();
+        let mut tokens = AnsiTokenizer::new(s);
+        for token in tokens.by_ref() {
+            let codes = match token {
+                AnsiToken::Sgr(codes) => codes,
+                AnsiToken::OscHyperlink { url } => {
+                    style.link = (!url.is_empty()).then(|| Hyperlink(url.to_string()));
+                    continue;
+                }
+                _ => return Err(StyleParseError::new(s, "expected only sgr and hyperlink sequences")),
+            };
+
+            let mut codes = codes.into_iter();
+            while let Some(code) = codes.next() {
+                match code {
+                    0 => style = Style { link: style.link.take(), ..Style::default() },
+                    1 => style.flags |= BOLD,
+                    2 => style.flags |= DIM,
+                    3 => style.flags |= ITALIC,
+                    4 => style.flags |= UNDERLINE,
+                    5 => style.flags |= BLINK,
+                    6 => style.flags |= RAPID_BLINK,
+                    7 => style.flags |= REVERSED,
+                    8 => style.flags |= HIDDEN,
+                    9 => style.flags |= CROSSED,
+                    21 => style.flags |= DOUBLE_UNDERLINE,
+                    22 => style.flags = style.flags.remove(BOLD | DIM),
+                    23 => style.flags = style.flags.remove(ITALIC),
+                    24 => style.flags = style.flags.remove(UNDERLINE | DOUBLE_UNDERLINE),
+                    25 => style.flags = style.flags.remove(BLINK | RAPID_BLINK),
+                    27 => style.flags = style.flags.remove(REVERSED),
+                    28 => style.flags = style.flags.remove(HIDDEN),
+                    29 => style.flags = style.flags.remove(CROSSED),
+                    30..=37 => style.fg = Some(NAMED_COLORS[(code - 30) as usize]),
+                    40..=47 => style.bg = Some(NAMED_COLORS[(code - 40) as usize]),
+                    90..=97 => style.fg = Some(NAMED_COLORS[(code - 82) as usize]),
+                    100..=107 => style.bg = Some(NAMED_COLORS[(code - 92) as usize]),
+                    38 => style.fg = Some(parse_sgr_color(&mut codes, s)?),
+                    48 => style.bg = Some(parse_sgr_color(&mut codes, s)?),
+                    39 => style.fg = None,
+                    49 => style.bg = None,
+                    _ => unknown.push(code),
+                }
+            }
+        }
+
+        if !tokens.remainder().is_empty() {
+            return Err(StyleParseError::new(s, "incomplete escape sequence"));
+        }
+        if strict && !unknown.is_empty() {
+            let codes = unknown.iter().map(|c| c.to_string()).collect::<Vec<_>>();
+            return Err(StyleParseError::new(s, format!("unknown sgr codes {}", codes.join(", "))));
+        }
+        Ok(style)
+    }
+
     /// Get a hash value for the given style. The hash value is useful for a hash key for both
     /// a map and a reference.
     pub fn hash_key(&self) -> u64 {
@@ -359,6 +1501,26 @@ impl Style {
         Style::default()
     }
 
+    /// Start a builder from a precomputed set of flags.
+    pub fn with_flags(flags: StyleFlag) -> Style {
+        Style { flags, ..Style::default() }
+    }
+
+    /// Paint the text with this style. Unlike the style itself the result can be padded,
+    /// Ex: `format!("{:>10}", style.apply("x"))`.
+    pub fn apply<D: Display>(&self, text: D) -> StyledStr {
+        StyledStr { style: self.clone(), text: text.to_string() }
+    }
+
+    /// Pick one of two built styles, Ex: `Style::either(ok, green, red)` for a status.
+    pub fn either(condition: bool, yes: Style, no: Style) -> Style {
+        if condition {
+            yes
+        } else {
+            no
+        }
+    }
+
     pub fn fg(mut self, color: Color) -> Self {
         self.fg = Some(color);
         self
@@ -369,15 +1531,28 @@ impl Style {
         self
     }
 
+    /// Set the foreground from a hex string, see [`Color::try_from_hex`].
+    pub fn fg_hex(self, hex: &str) -> Result<Self, ColorParseError> {
+        Ok(self.fg(Color::try_from_hex(hex)?))
+    }
+
+    /// Set the background from a hex string, see [`Color::try_from_hex`].
+    pub fn bg_hex(self, hex: &str) -> Result<Self, ColorParseError> {
+        Ok(self.bg(Color::try_from_hex(hex)?))
+    }
+
     pub fn link<L: Display>(self, link: L) -> Style {
         Style {
-            flags: self.flags,
-            fg: self.fg,
-            bg: self.bg,
             link: Some(Hyperlink::from(link.to_string())),
+            ..self
         }
     }
 
+    pub fn name(mut self, name: &'static str) -> Self {
+        self.name = Some(name);
+        self
+    }
+
     pub fn flags(mut self, flags: StyleFlag) -> Self {
         self.flags |= flags;
         self
@@ -413,12 +1588,248 @@ impl Style {
         self
     }
 
+    pub fn dim(mut self) -> Self {
+        self.flags |= DIM;
+        self
+    }
+
+    pub fn double_underline(mut self) -> Self {
+        self.flags |= DOUBLE_UNDERLINE;
+        self
+    }
+
+    pub fn rapid_blink(mut self) -> Self {
+        self.flags |= RAPID_BLINK;
+        self
+    }
+
+    pub fn hidden(mut self) -> Self {
+        self.flags |= HIDDEN;
+        self
+    }
+
     pub fn reset(mut self) -> Self {
         self.flags |= RESET;
         self
     }
+
+    /// Whether the style has the [`RESET`] flag. Reset dominates the other attributes, so the
+    /// style is reset with a bare `\x1b[0m` no matter which colors or flags are also set.
+    pub fn is_reset(&self) -> bool {
+        self.flags & RESET == RESET
+    }
+
+    /// Keep the style if the condition is true, otherwise use the default (empty) style.
+    ///
+    /// Ex: `Style::builder().bold().when(verbose)`
+    pub fn when(self, condition: bool) -> Style {
+        if condition {
+            self
+        } else {
+            Style::default()
+        }
+    }
+
+    /// Drop the parts of the style that the terminal doesn't support.
+    ///
+    /// Without ansi support the style is empty. Colors are removed if they require a higher
+    /// color support than the terminal has.
+    pub fn when_supported(mut self, caps: &Capabilities) -> Style {
+        if !caps.ansi {
+            return Style::default();
+        }
+        self.fg = self.fg.filter(|c| caps.supports(c));
+        self.bg = self.bg.filter(|c| caps.supports(c));
+        self
+    }
+
+    /// The numeric SGR parameters the style emits, Ex: `[1, 31]` for bold red, for other
+    /// terminal backends or custom sequences. Colors expand to their `38;5;n` and `38;2;r;g;b`
+    /// forms, the link isn't an SGR parameter and is left out.
+    pub fn sgr_codes(&self) -> Vec<u16> {
+        self.ansi()
+            .split(';')
+            .filter_map(|code| code.parse().ok())
+            .collect()
+    }
+
+    /// Layer another style over this one, Ex: a selection highlight over a syntax color.
+    ///
+    /// The colors, link, and name of `over` win when they are set, otherwise this style's are
+    /// kept. The flags of both styles are combined.
+    pub fn merge(&self, over: &Style) -> Style {
+        Style { flags: self.flags | over.flags, ..self.merge_flags_replace(over) }
+    }
+
+    /// Same as [`Style::merge`] except the flags of `over` replace this style's flags instead of
+    /// being combined with them.
+    pub fn merge_flags_replace(&self, over: &Style) -> Style {
+        Style {
+            flags: over.flags,
+            fg: over.fg.or(self.fg),
+            bg: over.bg.or(self.bg),
+            link: over.link.clone().or_else(|| self.link.clone()),
+            name: over.name.or(self.name),
+        }
+    }
+
+    /// The minimal sequence to go from this style to another style.
+    ///
+    /// Only the attributes that differ are reset or applied instead of resetting this style
+    /// and applying the other style in full.
+    pub fn transition(&self, to: &Style) -> String {
+        let mut ansi = Vec::new();
+
+        // Resetting a flag clears its whole reset family, so the kept flags of the family are
+        // turned back on, Ex: bold+dim to bold is `22;1`
+        let removed = self.flags & !to.flags;
+        if removed.0 > 0 {
+            ansi.push(removed.reset_ansi());
+        }
+        let added = (to.flags & !self.flags) | (to.flags & removed.reset_family());
+        if added.0 > 0 {
+            ansi.push(added.ansi());
+        }
+
+        if self.fg != to.fg {
+            match (to.fg, self.fg) {
+                (Some(fg), _) => ansi.push(fg.fg()),
+                (None, Some(fg)) => ansi.push(fg.reset_fg()),
+                (None, None) => {}
+            }
+        }
+        if self.bg != to.bg {
+            match (to.bg, self.bg) {
+                (Some(bg), _) => ansi.push(bg.bg()),
+                (None, Some(bg)) => ansi.push(bg.reset_bg()),
+                (None, None) => {}
+            }
+        }
+
+        let mut sequence = String::new();
+        if self.link != to.link {
+            match (&to.link, &self.link) {
+                (Some(link), _) => sequence.push_str(&link.sequence()),
+                (None, Some(link)) => sequence.push_str(&link.reset_sequence()),
+                (None, None) => {}
+            }
+        }
+        if !ansi.is_empty() {
+            sequence.push_str(&format!("\x1b[{}m", ansi.join(";")));
+        }
+        sequence
+    }
+
+    /// Same as [`Style::transition`] but colors are first downsampled to what the terminal
+    /// supports, so the transition never uses a color the terminal can't render.
+    pub fn transition_for(&self, to: &Style, caps: &Capabilities) -> String {
+        if !caps.ansi {
+            return String::new();
+        }
+        self.downsampled(caps.color).transition(&to.downsampled(caps.color))
+    }
+
+    /// Human readable descriptions of every difference going from this style to the other style,
+    /// Ex: `["bold added", "fg changed from red to #ff0000", "link https://example.com removed"]`.
+    ///
+    /// Empty when the styles are equal. Useful when a rendered output doesn't match what was
+    /// expected to find which attribute changed.
+    pub fn diff_report(&self, other: &Style) -> Vec<String> {
+        let mut report = Vec::new();
+        for flag in (other.flags & !self.flags).iter() {
+            report.push(format!("{} added", flag.describe()));
+        }
+        for flag in (self.flags & !other.flags).iter() {
+            report.push(format!("{} removed", flag.describe()));
+        }
+        match (self.is_reset(), other.is_reset()) {
+            (false, true) => report.push("reset added".to_string()),
+            (true, false) => report.push("reset removed".to_string()),
+            _ => {}
+        }
+
+        let mut compare = |field: &str, from: Option<String>, to: Option<String>| match (from, to) {
+            (Some(from), Some(to)) if from != to => report.push(format!("{} changed from {} to {}", field, from, to)),
+            (None, Some(to)) => report.push(format!("{} {} added", field, to)),
+            (Some(from), None) => report.push(format!("{} {} removed", field, from)),
+            _ => {}
+        };
+        let name = |color: &Color| match color.name_or_hex() {
+            // Colors that aren't exactly representable as hex are described by their variant
+            hex if hex.starts_with('#') && !matches!(color, Color::RGB { .. } | Color::RGBA { .. }) => format!("{:?} ({})", color, hex),
+            name => name,
+        };
+        if self.fg != other.fg {
+            compare("fg", self.fg.as_ref().map(name), other.fg.as_ref().map(name));
+        }
+        if self.bg != other.bg {
+            compare("bg", self.bg.as_ref().map(name), other.bg.as_ref().map(name));
+        }
+        compare("link", self.link.as_ref().map(|l| l.0.clone()), other.link.as_ref().map(|l| l.0.clone()));
+
+        report
+    }
+
+    /// Render the sample with the style after a plain text description of the style, Ex:
+    /// `bold red on #202020: <sample>`. Useful for catalogs of styles in examples and docs.
+    ///
+    /// The styled sample honors the terminal's [`CAPABILITIES`].
+    pub fn preview(&self, sample: &str) -> String {
+        self.preview_for(sample, &CAPABILITIES)
+    }
+
+    /// Same as [`Style::preview`] but the styled sample honors the given capabilities.
+    pub fn preview_for(&self, sample: &str, caps: &Capabilities) -> String {
+        let mut description = Vec::new();
+        if self.flags.0 > 0 {
+            description.push(self.flags.describe().replace('+', " "));
+        }
+        if let Some(fg) = self.fg {
+            description.push(fg.name_or_hex());
+        }
+        if let Some(bg) = self.bg {
+            description.push(format!("on {}", bg.name_or_hex()));
+        }
+        if let Some(link) = &self.link {
+            description.push(format!("linked to {}", link.0));
+        }
+        if description.is_empty() {
+            description.push("plain".to_string());
+        }
+
+        let none = Style::default();
+        format!(
+            "{}: {}{}{}",
+            description.join(" "),
+            none.transition_for(self, caps),
+            sample,
+            self.transition_for(&none, caps)
+        )
+    }
+
+    fn downsampled(&self, support: ColorSupport) -> Style {
+        Style {
+            fg: self.fg.and_then(|c| c.downsample(support)),
+            bg: self.bg.and_then(|c| c.downsample(support)),
+            ..self.clone()
+        }
+    }
+}
+
+/// Add flags to a style, same as [`Style::flags`].
+///
+/// Ex: `Style::builder().fg(Color::RED) + BOLD + ITALIC`
+impl Add<StyleFlag> for Style {
+    type Output = Style;
+
+    fn add(self, flags: StyleFlag) -> Self::Output {
+        self.flags(flags)
+    }
 }
 
+/// Writes the escape sequence, or the reset with `{:-}`.
+///
+/// Width, fill, and alignment are ignored by design, use [`Style::apply`] to pad styled text.
 impl Display for Style {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         if f.sign_minus() {
@@ -429,6 +1840,54 @@ impl Display for Style {
     }
 }
 
+/// A style inlined in a `format::format!` template, Ex:
+/// `format!("{s:style}warning{s:/style}", s = Style::builder().bold())`. Rendered on its own, `{s}`,
+/// it is the style's opening sequence.
+impl format::Argument for Style {
+    fn render(&self) -> String {
+        self.sequence()
+    }
+
+    fn style(&self) -> Option<(String, String)> {
+        Some((self.sequence(), self.reset_sequence()))
+    }
+}
+
+/// Text painted with a style, created with [`Style::apply`].
+///
+/// `Display` honors the width, fill, alignment, and precision of the formatter by the visible
+/// width of the text, so `format!("{:>10}", style.apply("x"))` pads to 10 columns. The padding
+/// is outside of the style.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StyledStr {
+    pub style: Style,
+    pub text: String,
+}
+
+impl Display for StyledStr {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let text = match f.precision() {
+            Some(precision) => self.text.chars().take(precision).collect::<String>(),
+            None => self.text.clone(),
+        };
+        let padding = f.width().unwrap_or(0).saturating_sub(UnicodeWidthStr::width(text.as_str()));
+        let (before, after) = match f.align() {
+            Some(Alignment::Right) => (padding, 0),
+            Some(Alignment::Center) => (padding / 2, padding - padding / 2),
+            Some(Alignment::Left) | None => (0, padding),
+        };
+        let fill = f.fill();
+        for _ in 0..before {
+            f.write_char(fill)?;
+        }
+        write!(f, "{}{}{}", self.style.sequence(), text, self.style.reset_sequence())?;
+        for _ in 0..after {
+            f.write_char(fill)?;
+        }
+        Ok(())
+    }
+}
+
 impl AnsiSequence for Style {
     fn ansi(&self) -> String {
         let mut ansi = Vec::new();
@@ -445,7 +1904,7 @@ impl AnsiSequence for Style {
             ansi.push(bg.bg());
         }
 
-        if ansi.len() > 0 {
+        if !ansi.is_empty() {
             ansi.join(";")
         } else {
             String::new()
@@ -453,8 +1912,8 @@ impl AnsiSequence for Style {
     }
 
     fn reset_ansi(&self) -> String {
-        if self.flags & RESET == RESET {
-            return "\x1b[0m".to_string();
+        if self.is_reset() {
+            return "0".to_string();
         }
 
         let mut ansi = Vec::new();
@@ -468,7 +1927,7 @@ impl AnsiSequence for Style {
             ansi.push(self.flags.reset_ansi());
         }
 
-        if ansi.len() > 0 {
+        if !ansi.is_empty() {
             ansi.join(";")
         } else {
             String::new()
@@ -482,10 +1941,12 @@ impl AnsiSequence for Style {
             }
             None => String::new()
         };
-        if link.len() == 0 && self.ansi().len() == 0 {
-            return String::new();
+        // Without any attributes `\x1b[m` would reset everything, so only the link is opened
+        let ansi = self.ansi();
+        if ansi.is_empty() {
+            return link;
         }
-        format!("{}\x1b[{}m", link, self.ansi())
+        format!("{}\x1b[{}m", link, ansi)
     }
 
     fn reset_sequence(&self) -> String {
@@ -496,9 +1957,600 @@ impl AnsiSequence for Style {
             None => String::new()
         };
 
-        if link.len() == 0 && self.reset_ansi().len() == 0 {
-            return String::new();
+        let reset = self.reset_ansi();
+        if reset.is_empty() {
+            return link;
+        }
+        format!("\x1b[{}m{}", reset, link)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn packed_color() {
+        assert_eq!(Color::from(0xff8800u32), Color::RGB { r: 255, g: 136, b: 0 });
+        assert_eq!(Color::from(0xffff8800u32), Color::RGB { r: 255, g: 136, b: 0 });
+        assert_eq!(Color::from(200u8), Color::Ansi(200));
+        assert_eq!(color!(200), Color::Ansi(200));
+    }
+
+    #[test]
+    fn hex_is_case_insensitive() {
+        assert_eq!(Color::try_from_hex("#ABCDEF"), Color::try_from_hex("#abcdef"));
+        assert_eq!(Color::try_from_hex("#AbC"), Color::try_from_hex("#aabbcc"));
+        assert_eq!(Color::try_from_hex("#abcDEF"), Color::try_from_hex("#abcdef"));
+        assert_eq!(Color::try_from_hex("#abcdef"), Ok(Color::RGB { r: 0xab, g: 0xcd, b: 0xef }));
+    }
+
+    #[test]
+    fn cymk_primaries() {
+        assert_eq!(Color::CYMK { c: 1.0, y: 0.0, m: 0.0, k: 0.0 }.to_rgb(), (0, 255, 255));
+        assert_eq!(Color::CYMK { c: 0.0, y: 0.0, m: 1.0, k: 0.0 }.to_rgb(), (255, 0, 255));
+        assert_eq!(Color::CYMK { c: 0.0, y: 1.0, m: 0.0, k: 0.0 }.to_rgb(), (255, 255, 0));
+        assert_eq!(Color::CYMK { c: 0.0, y: 0.0, m: 0.0, k: 1.0 }.to_rgb(), (0, 0, 0));
+    }
+
+    #[test]
+    fn oklab_reference_values() {
+        let close = |(a, b, c): (f32, f32, f32), (x, y, z): (f32, f32, f32)| {
+            (a - x).abs() < 1e-3 && (b - y).abs() < 1e-3 && (c - z).abs() < 1e-3
+        };
+        assert!(close(oklab((255, 255, 255)), (1.0, 0.0, 0.0)));
+        assert!(close(oklab((0, 0, 0)), (0.0, 0.0, 0.0)));
+        assert!(close(oklab((255, 0, 0)), (0.62796, 0.22486, 0.12585)));
+        assert!(close(oklab((0, 255, 0)), (0.86644, -0.23389, 0.17950)));
+        assert!(close(oklab((0, 0, 255)), (0.45201, -0.03246, -0.31153)));
+    }
+
+    #[test]
+    fn oklab_round_trip() {
+        for rgb in [(0, 0, 0), (255, 255, 255), (255, 0, 0), (12, 200, 99), (128, 64, 240), (250, 250, 1)] {
+            let (r, g, b) = oklab_rgb(oklab(rgb));
+            assert!(r.abs_diff(rgb.0) <= 1 && g.abs_diff(rgb.1) <= 1 && b.abs_diff(rgb.2) <= 1, "{:?}", rgb);
+        }
+    }
+
+    #[test]
+    fn style_sequences() {
+        let style = Style::builder().fg(Color::RED).bg(Color::BLUE).bold().link("https://x.y");
+        assert_eq!(style.sequence(), "\x1b]8;;https://x.y\x1b\\\x1b[1;31;44m");
+        assert_eq!(style.reset_sequence(), "\x1b[39;49;22m\x1b]8;;\x1b\\");
+
+        let style = Style::builder().italic();
+        assert_eq!(style.sequence(), "\x1b[3m");
+        assert_eq!(style.reset_sequence(), "\x1b[23m");
+
+        let style = Style::builder().link("https://x.y");
+        assert_eq!(style.sequence(), "\x1b]8;;https://x.y\x1b\\");
+        assert_eq!(style.reset_sequence(), "\x1b]8;;\x1b\\");
+
+        assert_eq!(Style::default().sequence(), "");
+        assert_eq!(Style::default().reset_sequence(), "");
+    }
+
+    #[test]
+    fn transition_turns_the_kept_family_back_on() {
+        use flags::*;
+
+        let transition = |from: StyleFlag, to: StyleFlag| Style::with_flags(from).transition(&Style::with_flags(to));
+        assert_eq!(transition(BOLD | DIM, BOLD), "\x1b[22;1m");
+        assert_eq!(transition(UNDERLINE | DOUBLE_UNDERLINE, UNDERLINE), "\x1b[24;4m");
+        assert_eq!(transition(UNDERLINE | DOUBLE_UNDERLINE, DOUBLE_UNDERLINE), "\x1b[24;21m");
+        assert_eq!(transition(BLINK | RAPID_BLINK, BLINK), "\x1b[25;5m");
+        assert_eq!(transition(BOLD, BOLD | DIM), "\x1b[2m");
+        assert_eq!(transition(BOLD, ITALIC), "\x1b[22;3m");
+    }
+
+    #[test]
+    fn out_of_range_hue_wraps() {
+        let hash = |color: Color| {
+            let mut hasher = DefaultHasher::new();
+            color.hash(&mut hasher);
+            hasher.finish()
+        };
+
+        let (wrapped, color) = (Color::HSL { h: 400, s: 1.0, l: 0.5 }, Color::HSL { h: 40, s: 1.0, l: 0.5 });
+        assert_eq!(wrapped, color);
+        assert_eq!(hash(wrapped), hash(color));
+        assert_eq!(Color::HSV { h: 720, s: 1.0, v: 1.0 }, Color::HSV { h: 0, s: 1.0, v: 1.0 });
+    }
+
+    #[test]
+    fn nested_format_styles_restore_the_outer_style() {
+        let (bold, red) = (Style::builder().bold(), Style::builder().fg(Color::RED));
+        assert_eq!(
+            format::format!("{a:style}x{b:style}y{:/style}z{:/style}", a = bold.clone(), b = red),
+            "\x1b[1mx\x1b[31my\x1b[39m\x1b[1mz\x1b[22m"
+        );
+        assert_eq!(format::format!("{:style}x{:/style}", bold), "\x1b[1mx\x1b[22m");
+    }
+
+    #[test]
+    fn with_alpha_keeps_the_rgb() {
+        for color in [Color::RED, Color::BrightCyan, Color::Ansi(200), Color::RGB { r: 1, g: 2, b: 3 }, color!(hsl 217 69% 68%)] {
+            assert_eq!(color.with_alpha(255).to_rgb(), color.to_rgb(), "{:?}", color);
         }
-        format!("\x1b[{}m{}", self.reset_ansi(), link)
+        assert_eq!(color!(red / 50%), Color::RGBA { r: 205, g: 0, b: 0, a: 128 });
+        assert_eq!(color!(blue, 0%), Color::RGBA { r: 0, g: 0, b: 238, a: 0 });
+    }
+
+    #[test]
+    fn parse_colors() {
+        assert_eq!("#ff8800".parse::<Color>(), Ok(Color::RGB { r: 255, g: 136, b: 0 }));
+        assert_eq!("#ff880080".parse::<Color>(), Ok(Color::RGBA { r: 255, g: 136, b: 0, a: 128 }));
+        assert_eq!("255, 0, 0".parse::<Color>(), Ok(Color::RGB { r: 255, g: 0, b: 0 }));
+        assert_eq!(" 1,2 ,3 ".parse::<Color>(), Ok(Color::RGB { r: 1, g: 2, b: 3 }));
+        assert_eq!("rgb(1, 2, 3)".parse::<Color>(), Ok(Color::RGB { r: 1, g: 2, b: 3 }));
+        assert_eq!("200".parse::<Color>(), Ok(Color::Ansi(200)));
+        assert_eq!("Bright_Red".parse::<Color>(), Ok(Color::BrightRed));
+        assert!("300,0,0".parse::<Color>().is_err());
+        assert!("1,2".parse::<Color>().is_err());
+        assert!("#ff88".parse::<Color>().is_ok());
+        assert!("#ff8".parse::<Color>().is_ok());
+        assert!("#ff88000".parse::<Color>().is_err());
+        assert_eq!(Color::try_from_hex("ff8800"), "#ff8800".parse::<Color>());
+    }
+
+    #[test]
+    fn hex_builders() {
+        let style = Style::builder().fg_hex("#ff8800").unwrap().bg_hex("000").unwrap();
+        assert_eq!(style.fg, Some(Color::RGB { r: 255, g: 136, b: 0 }));
+        assert_eq!(style.bg, Some(Color::RGB { r: 0, g: 0, b: 0 }));
+
+        let error = Style::builder().fg_hex("#ff88zz").unwrap_err();
+        assert_eq!(error.value, "#ff88zz");
+        assert!(Style::builder().bg_hex("#12345").is_err());
+    }
+
+    #[test]
+    fn websafe_snaps_each_channel() {
+        assert_eq!(Color::RGB { r: 30, g: 100, b: 230 }.to_websafe(), Color::RGB { r: 51, g: 102, b: 255 });
+        assert_eq!(Color::RGB { r: 128, g: 127, b: 25 }.to_websafe(), Color::RGB { r: 153, g: 102, b: 0 });
+        assert_eq!(Color::RGB { r: 255, g: 0, b: 26 }.to_websafe(), Color::RGB { r: 255, g: 0, b: 51 });
+        assert_eq!(Color::Default.to_websafe(), Color::Default);
+    }
+
+    #[test]
+    fn keyword_colors() {
+        let transparent = Color::RGBA { r: 0, g: 0, b: 0, a: 0 };
+        assert_eq!("transparent".parse::<Color>(), Ok(transparent));
+        assert_eq!("none".parse::<Color>(), Ok(Color::Default));
+        assert_eq!(" Default ".parse::<Color>(), Ok(Color::Default));
+
+        // Both render as the terminal's own color, so a transparent background lets it show
+        assert_eq!(Style::builder().bg(transparent).sequence(), "\x1b[49m");
+        assert_eq!(Style::builder().fg(Color::Default).sequence(), "\x1b[39m");
+    }
+
+    #[test]
+    fn when_keeps_or_drops_the_style() {
+        let style = Style::builder().bold().fg(Color::RGB { r: 1, g: 2, b: 3 });
+        assert_eq!(style.clone().when(true), style);
+        assert_eq!(style.clone().when(false), Style::default());
+
+        let caps = Capabilities { ansi: true, color: ColorSupport::Standard };
+        let supported = style.clone().bg(Color::RED).when_supported(&caps);
+        assert_eq!(supported, Style::builder().bold().bg(Color::RED));
+        let caps = Capabilities { ansi: true, color: ColorSupport::TrueColor };
+        assert_eq!(style.clone().when_supported(&caps), style);
+        let caps = Capabilities { ansi: false, color: ColorSupport::TrueColor };
+        assert_eq!(style.when_supported(&caps), Style::default());
+    }
+
+    #[test]
+    fn composite_over_the_terminal_default_is_straight_rgb() {
+        let translucent = Color::RGBA { r: 200, g: 100, b: 50, a: 128 };
+        assert_eq!(translucent.composite(BlendBackground::TerminalDefault), Color::RGB { r: 200, g: 100, b: 50 });
+
+        // Over a known background the channels are mixed by the alpha
+        let over = translucent.composite(BlendBackground::Color(Color::RGB { r: 0, g: 0, b: 0 }));
+        assert_eq!(over, Color::RGB { r: 100, g: 50, b: 25 });
+        assert_eq!(Color::RED.composite(BlendBackground::TerminalDefault), Color::RED);
+    }
+
+    #[test]
+    fn rgb_f_scales_and_clamps() {
+        assert_eq!(Color::rgb_f(0.5, 0.0, 1.0), Color::RGB { r: 128, g: 0, b: 255 });
+        assert_eq!(Color::rgb_f(-0.5, 1.5, 0.2), Color::RGB { r: 0, g: 255, b: 51 });
+    }
+
+    #[test]
+    fn ansi256_prefers_the_gray_ramp_and_the_cube() {
+        assert_eq!(Color::RGB { r: 128, g: 128, b: 128 }.to_ansi256(), Color::Ansi(244));
+        assert_eq!(Color::RGB { r: 102, g: 100, b: 101 }.to_ansi256(), Color::Ansi(241));
+        assert_eq!(Color::RGB { r: 240, g: 10, b: 10 }.to_ansi256(), Color::Ansi(196));
+        assert_eq!(Color::RGB { r: 0, g: 135, b: 255 }.to_ansi256(), Color::Ansi(33));
+    }
+
+    #[test]
+    fn hyperlink_components_are_encoded() {
+        let link = Hyperlink::new("file", "", "/path with space/x.rs");
+        assert_eq!(link.0, "file:///path%20with%20space/x.rs");
+        assert_eq!(link.sequence(), "\x1b]8;;file:///path%20with%20space/x.rs\x1b\\");
+
+        let link = Hyperlink::builder()
+            .scheme("https")
+            .host("example.com")
+            .path("docs/a;b")
+            .query("q", "a&b=c")
+            .fragment("top level")
+            .build();
+        assert_eq!(link.0, "https://example.com/docs/a%3Bb?q=a%26b%3Dc#top%20level");
+    }
+
+    #[test]
+    fn with_flags_seeds_the_style() {
+        use flags::{BOLD, ITALIC};
+
+        let style = Style::with_flags(BOLD | ITALIC);
+        assert_eq!(style, Style::builder().bold().italic());
+        assert_eq!(style.sequence(), "\x1b[1;3m");
+    }
+
+    #[test]
+    fn transition_for_downsamples_to_the_color_support() {
+        let from = Style::builder().fg(Color::RGB { r: 255, g: 0, b: 0 });
+        let to = Style::builder().fg(Color::RGB { r: 0, g: 135, b: 255 }).bg(Color::RGB { r: 250, g: 0, b: 0 });
+        let caps = |ansi: bool, color: ColorSupport| Capabilities { ansi, color };
+
+        assert_eq!(from.transition_for(&to, &caps(true, ColorSupport::EightBit)), "\x1b[38;5;33;48;5;196m");
+        assert_eq!(from.transition_for(&to, &caps(true, ColorSupport::TrueColor)), "\x1b[38;2;0;135;255;48;2;250;0;0m");
+        // Colors that downsample to the same palette color don't change
+        let close = Style::builder().fg(Color::RGB { r: 250, g: 0, b: 0 });
+        assert_eq!(from.transition_for(&close, &caps(true, ColorSupport::EightBit)), "");
+        assert_eq!(from.transition_for(&to, &caps(false, ColorSupport::TrueColor)), "");
+    }
+
+    #[test]
+    fn default_color_is_the_reset_code() {
+        assert_eq!(Color::default(), Color::Default);
+        assert_eq!(Color::default().fg(), "39");
+        assert_eq!(Color::default().bg(), "49");
+        assert_eq!(Style::builder().fg(Color::default()).sequence(), "\x1b[39m");
+    }
+
+    #[test]
+    fn name_is_ignored_by_equality_and_hash() {
+        let hash = |style: &Style| {
+            let mut hasher = DefaultHasher::new();
+            style.hash(&mut hasher);
+            hasher.finish()
+        };
+
+        let style = Style::builder().bold().fg(Color::RED);
+        let error = style.clone().name("error");
+        let warning = style.clone().name("warning");
+        assert_eq!(error, warning);
+        assert_eq!(error, style);
+        assert_eq!(hash(&error), hash(&warning));
+        assert_eq!(error.to_string(), style.to_string());
+        assert!(format!("{error:?}").contains("\"error\""));
+    }
+
+    #[test]
+    fn hsl_and_hsv_round_trip() {
+        let close = |(r, g, b): (u8, u8, u8), (er, eg, eb): (u8, u8, u8)| {
+            r.abs_diff(er) <= 1 && g.abs_diff(eg) <= 1 && b.abs_diff(eb) <= 1
+        };
+
+        let orange = Color::RGB { r: 255, g: 136, b: 0 };
+        let (h, s, l) = orange.to_hsl();
+        assert_eq!(h, 32);
+        assert!((s - 1.0).abs() < 0.01 && (l - 0.5).abs() < 0.01);
+        assert!(close(Color::HSL { h, s, l }.to_rgb(), (255, 136, 0)));
+
+        let teal = Color::RGB { r: 40, g: 160, b: 150 };
+        let (h, s, v) = teal.to_hsv();
+        assert!(close(Color::HSV { h, s, v }.to_rgb(), (40, 160, 150)));
+
+        let (h, s, l) = Color::RGB { r: 128, g: 128, b: 128 }.to_hsl();
+        assert_eq!((h, s), (0, 0.0));
+        assert!((l - 128.0 / 255.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn add_flags_to_a_colored_style() {
+        let style = Style::builder().fg(Color::RED) + BOLD + ITALIC;
+        assert_eq!(style, Style::builder().fg(Color::RED).flags(BOLD | ITALIC));
+        assert_eq!(style.fg, Some(Color::RED));
+        assert_eq!(style.to_string(), "\x1b[1;3;31m");
+    }
+
+    #[test]
+    fn empty_ansi_has_no_sequence() {
+        assert_eq!(StyleFlag::default().sequence(), "");
+        assert_eq!(StyleFlag::default().reset_sequence(), "");
+        assert_eq!(Style::default().sequence(), "");
+        assert_eq!(Style::default().reset_sequence(), "");
+        assert_eq!(Style::default().to_string(), "");
+
+        // A color is never empty, its sequence is the foreground
+        assert_eq!(Color::RED.sequence(), "\x1b[31m");
+        assert_eq!(Color::RED.reset_sequence(), "\x1b[39m");
+        assert_eq!(Color::RED.sequence(), Color::RED.to_string());
+    }
+
+    #[test]
+    fn name_or_hex_round_trips() {
+        for color in [
+            Color::RED,
+            Color::WHITE,
+            Color::Default,
+            Color::Ansi(200),
+            Color::RGB { r: 255, g: 136, b: 0 },
+        ] {
+            assert_eq!(color.name_or_hex().parse::<Color>().ok(), Some(color));
+        }
+        assert_eq!(Color::RED.name_or_hex(), "red");
+        assert_eq!(Color::RGB { r: 255, g: 136, b: 0 }.name_or_hex(), "#ff8800");
+    }
+
+    #[test]
+    fn link_only_style_has_no_sgr() {
+        let style = Style::builder().link("https://example.com");
+        assert_eq!(style.sequence(), "\x1b]8;;https://example.com\x1b\\");
+        assert_eq!(style.reset_sequence(), "\x1b]8;;\x1b\\");
+
+        let bold = Style::builder().bold().link("https://example.com");
+        assert_eq!(bold.sequence(), "\x1b]8;;https://example.com\x1b\\\x1b[1m");
+        assert_eq!(bold.reset_sequence(), "\x1b[22m\x1b]8;;\x1b\\");
+    }
+
+    #[test]
+    fn lab_distance_picks_the_perceptual_nearest() {
+        // A medium blue is closer to cyan by rgb channels but looks much closer to bright blue
+        let blue = Color::RGB { r: 0, g: 100, b: 200 };
+        assert_eq!(blue.to_ansi16_by(ColorDistance::Rgb), Color::CYAN);
+        assert_eq!(blue.to_ansi16_by(ColorDistance::Lab), Color::Ansi(12));
+        assert_eq!(blue.to_ansi16(), blue.to_ansi16_by(ColorDistance::default()));
+    }
+
+    #[test]
+    fn preview_has_the_description_and_the_sequence() {
+        let caps = Capabilities { ansi: true, color: ColorSupport::TrueColor };
+        let style = Style::builder().bold().fg(Color::RED);
+        assert_eq!(style.preview_for("text", &caps), "bold red: \x1b[1;31mtext\x1b[22;39m");
+        assert_eq!(Style::default().preview_for("text", &caps), "plain: text");
+
+        let no_ansi = Capabilities { ansi: false, color: ColorSupport::TrueColor };
+        assert_eq!(style.preview_for("text", &no_ansi), "bold red: text");
+    }
+
+    #[test]
+    fn multi_gradient_interpolates_between_stops() {
+        let red = Color::RGB { r: 255, g: 0, b: 0 };
+        let green = Color::RGB { r: 0, g: 255, b: 0 };
+        let blue = Color::RGB { r: 0, g: 0, b: 255 };
+        let expected = vec![
+            red,
+            Color::RGB { r: 128, g: 128, b: 0 },
+            green,
+            Color::RGB { r: 0, g: 128, b: 128 },
+            blue,
+        ];
+        assert_eq!(Color::multi_gradient(&[(0.0, red), (0.5, green), (1.0, blue)], 5), expected);
+        // Stops are sorted and clamped
+        assert_eq!(Color::multi_gradient(&[(2.0, blue), (-1.0, red), (0.5, green)], 5), expected);
+        assert!(Color::multi_gradient(&[], 3).is_empty());
+    }
+
+    #[test]
+    fn diff_report_lists_every_difference() {
+        let from = Style::builder().italic().fg(Color::RED).link("https://example.com");
+        let to = Style::builder().bold().fg(Color::RGB { r: 255, g: 0, b: 0 }).bg(Color::BLUE);
+        assert_eq!(
+            from.diff_report(&to),
+            vec![
+                "bold added",
+                "italic removed",
+                "fg changed from red to #ff0000",
+                "bg blue added",
+                "link https://example.com removed",
+            ]
+        );
+        assert!(from.diff_report(&from.clone()).is_empty());
+    }
+
+    #[test]
+    fn hwb_tints_and_shades_the_hue() {
+        assert_eq!(color!(hwb 0, 0%, 0%).fg(), "38;2;255;0;0");
+        assert_eq!(color!(hwb 0, 50%, 0%).fg(), "38;2;255;128;128");
+        assert_eq!(color!(hwb 120 0% 50%).fg(), "38;2;0;128;0");
+        // Whiteness and blackness over 1.0 together are normalized into a gray
+        assert_eq!(Color::hwb(0, 0.75, 0.75), Ok(Color::HWB { h: 0, w: 0.5, b: 0.5 }));
+        assert_eq!(Color::HWB { h: 0, w: 0.75, b: 0.75 }.fg(), "38;2;128;128;128");
+        assert!(Color::hwb(0, 1.5, 0.0).is_err());
+        assert!(Color::hwb(360, 0.0, 0.0).is_err());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn relative_path_resolves_against_the_base() {
+        let link = Hyperlink::from_path(Path::new("src/../my file.rs"), Some(Path::new("/no-such-repo"))).unwrap();
+        assert_eq!(link.0, "file:///no-such-repo/my%20file.rs");
+        let link = Hyperlink::from_path(Path::new("/no-such-repo/a"), Some(Path::new("/other"))).unwrap();
+        assert_eq!(link.0, "file:///no-such-repo/a");
+    }
+
+    #[test]
+    #[cfg(windows)]
+    fn windows_drive_path() {
+        let link = Hyperlink::from_path(Path::new(r"C:\no-such-dir\..\file.txt"), None).unwrap();
+        assert_eq!(link.0, "file:///C:/file.txt");
+        let link = Hyperlink::from_path(Path::new(r"src\main.rs"), Some(Path::new(r"D:\repo"))).unwrap();
+        assert_eq!(link.0, "file:///D:/repo/src/main.rs");
+    }
+
+    #[test]
+    fn either_picks_by_the_condition() {
+        let green = Style::builder().fg(Color::GREEN);
+        let red = Style::builder().fg(Color::RED).bold();
+        assert_eq!(Style::either(true, green.clone(), red.clone()), green);
+        assert_eq!(Style::either(false, green, red.clone()), red);
+    }
+
+    #[test]
+    fn styled_str_pads_outside_the_style() {
+        let bold = Style::builder().bold();
+        assert_eq!(format!("{:>5}", bold.apply("x")), "    \x1b[1mx\x1b[22m");
+        assert_eq!(format!("{:*^5}", bold.apply("x")), "**\x1b[1mx\x1b[22m**");
+        assert_eq!(format!("{:.2}|", bold.apply("abc")), "\x1b[1mab\x1b[22m|");
+        // The style itself ignores the width
+        assert_eq!(format!("{:>5}", bold), "\x1b[1m");
+    }
+
+    #[test]
+    fn gradient_includes_both_ends() {
+        let black = Color::RGB { r: 0, g: 0, b: 0 };
+        let white = Color::RGB { r: 255, g: 255, b: 255 };
+        assert_eq!(
+            black.gradient(&white, 3).collect::<Vec<_>>(),
+            vec![black, Color::RGB { r: 128, g: 128, b: 128 }, white]
+        );
+        assert_eq!(black.gradient(&white, 1).collect::<Vec<_>>(), vec![black]);
+        assert_eq!(black.gradient(&white, 0).count(), 0);
+    }
+
+    #[test]
+    fn channels_of_a_named_color() {
+        let (r, g, b) = Color::RED.to_rgb();
+        assert_eq!((Color::RED.red(), Color::RED.green(), Color::RED.blue()), (r, g, b));
+        assert_eq!(Color::RED.with_green(128), Color::RGB { r, g: 128, b });
+        assert_eq!(Color::RGB { r: 1, g: 2, b: 3 }.with_red(9).with_blue(7), Color::RGB { r: 9, g: 2, b: 7 });
+    }
+
+    #[test]
+    fn sgr_codes_of_bold_red_and_rgb() {
+        assert_eq!(Style::builder().bold().fg(Color::RED).sgr_codes(), vec![1, 31]);
+        assert_eq!(Style::builder().fg(Color::RGB { r: 255, g: 136, b: 0 }).sgr_codes(), vec![38, 2, 255, 136, 0]);
+        assert_eq!(Style::builder().bg(Color::Ansi(200)).sgr_codes(), vec![48, 5, 200]);
+        assert!(Style::default().sgr_codes().is_empty());
+    }
+
+    #[test]
+    fn shades_lighten_and_keep_the_hue() {
+        let red = Color::RGB { r: 255, g: 0, b: 0 };
+        let shades = red.shades(5);
+        assert_eq!(shades.len(), 5);
+        let hsl = shades.iter().map(Color::to_hsl).collect::<Vec<_>>();
+        assert!(hsl.windows(2).all(|pair| pair[0].2 < pair[1].2));
+        assert!(hsl.iter().all(|(h, _, _)| *h == 0));
+        assert!((hsl[0].2 - 0.1).abs() < 0.01 && (hsl[4].2 - 0.9).abs() < 0.01);
+        assert_eq!(red.shades_between(1, 0.2, 0.4), vec![Color::HSL { h: 0, s: 1.0, l: 0.3 }]);
+    }
+
+    #[test]
+    fn grayscale_within_tolerance() {
+        assert!(Color::RGB { r: 128, g: 128, b: 128 }.is_grayscale());
+        assert!(Color::RGB { r: 128, g: 130, b: 131 }.is_grayscale());
+        assert!(!Color::RGB { r: 128, g: 132, b: 128 }.is_grayscale());
+        assert!(!Color::RGB { r: 255, g: 136, b: 0 }.is_grayscale());
+        assert!(Color::Ansi(240).is_grayscale() && Color::WHITE.is_grayscale());
+        assert!(!Color::Ansi(196).is_grayscale() && !Color::Default.is_grayscale());
+    }
+
+    #[test]
+    fn reset_dominates_the_other_attributes() {
+        let reset = Style::builder().flags(RESET);
+        let colored = Style::builder().flags(RESET | BOLD).fg(Color::RED);
+        assert!(reset.is_reset() && colored.is_reset());
+        assert!(!Style::builder().bold().fg(Color::RED).is_reset());
+        assert_eq!(reset.reset_sequence(), "\x1b[0m");
+        assert_eq!(colored.reset_sequence(), "\x1b[0m");
+    }
+
+    #[test]
+    fn blend_mixes_the_channels() {
+        let (black, white) = (Color::RGB { r: 0, g: 0, b: 0 }, Color::RGB { r: 255, g: 255, b: 255 });
+        assert_eq!(black.blend(&white, 0.5), Color::RGB { r: 128, g: 128, b: 128 });
+        assert_eq!(black.blend_srgb(&white, 0.5), Color::RGB { r: 188, g: 188, b: 188 });
+        // The ends are returned as is and `t` is clamped
+        assert_eq!(Color::RED.blend(&white, 0.0), Color::RED);
+        assert_eq!(Color::RED.blend(&white, 2.0), white);
+        assert_eq!(Color::RED.blend(&white, f32::NAN), Color::RED);
+    }
+
+    #[test]
+    fn dim_shares_the_bold_reset() {
+        assert_eq!(DIM, StyleFlag(128));
+        assert_eq!(DIM.describe(), "dim");
+        assert_eq!(Style::builder().dim().sequence(), "\x1b[2m");
+        assert_eq!(Style::builder().dim().reset_sequence(), "\x1b[22m");
+        assert_eq!(Style::builder().bold().dim().reset_sequence(), "\x1b[22m");
+    }
+
+    #[test]
+    fn downgrade_to_the_color_support() {
+        let orange = Color::RGB { r: 255, g: 135, b: 0 };
+        assert_eq!(orange.downgrade(ColorSupport::TrueColor), orange);
+        assert_eq!(orange.downgrade(ColorSupport::EightBit), Color::Ansi(208));
+        assert_eq!(orange.downgrade(ColorSupport::Standard).palette_index().map(|i| i < 16), Some(true));
+        assert_eq!(orange.downgrade(ColorSupport::None), Color::Default);
+        assert_eq!(Color::RED.downgrade(ColorSupport::Standard), Color::RED);
+    }
+
+    #[test]
+    fn hex_alpha_round_trips() {
+        let color = Color::RGBA { r: 255, g: 136, b: 0, a: 128 };
+        assert_eq!(color.alpha(), 128);
+        assert_eq!(Color::RED.alpha(), 255);
+        assert_eq!(color.name_or_hex(), "#ff880080");
+        assert_eq!(color.name_or_hex().parse::<Color>(), Ok(color));
+    }
+
+    #[test]
+    fn hidden_is_concealed() {
+        assert_eq!(Style::builder().hidden().sequence(), "\x1b[8m");
+        assert_eq!(Style::builder().hidden().reset_sequence(), "\x1b[28m");
+        assert_eq!(HIDDEN.describe(), "hidden");
+    }
+
+    #[test]
+    fn merge_layers_over_the_base() {
+        let base = Style::builder().bold().fg(Color::RED).bg(Color::BLUE);
+        let over = Style::builder().italic().fg(Color::GREEN);
+        assert_eq!(base.merge(&over), Style::builder().bold().italic().fg(Color::GREEN).bg(Color::BLUE));
+        assert_eq!(base.merge_flags_replace(&over), Style::builder().italic().fg(Color::GREEN).bg(Color::BLUE));
+        assert_eq!(base.merge(&Style::default()), base);
+    }
+
+    #[test]
+    fn parse_ansi_reads_back_the_sequence() {
+        let style = Style::builder().bold().underline().fg(Color::RED).bg(Color::RGB { r: 1, g: 2, b: 3 });
+        assert_eq!(Style::parse_ansi(&style.sequence()), Ok(style));
+        assert_eq!(
+            Style::parse_ansi("\x1b[1m\x1b[0;3;38;5;200m"),
+            Ok(Style::builder().italic().fg(Color::Ansi(200)))
+        );
+        // Unknown codes are only an error when strict
+        assert_eq!(Style::parse_ansi("\x1b[1;99m"), Ok(Style::builder().bold()));
+        assert!(Style::parse_ansi_strict("\x1b[1;99m").is_err());
+        assert!(Style::parse_ansi("\x1b[38;5m").is_err());
+        assert!(Style::parse_ansi("\x1b[2J").is_err());
+    }
+
+    #[test]
+    fn to_rgb_of_every_kind_of_color() {
+        assert_eq!(Color::RED.to_rgb(), (205, 0, 0));
+        assert_eq!(Color::Ansi(196).to_rgb(), (255, 0, 0));
+        assert_eq!(Color::Ansi(244).to_rgb(), (128, 128, 128));
+        assert_eq!(Color::RGBA { r: 1, g: 2, b: 3, a: 4 }.to_rgb(), (1, 2, 3));
+        assert_eq!(Color::HSL { h: 120, s: 1.0, l: 0.5 }.to_rgb(), (0, 255, 0));
+        assert_eq!(Color::HSV { h: 240, s: 1.0, v: 1.0 }.to_rgb(), (0, 0, 255));
+        assert_eq!(Color::CYMK { c: 0.0, y: 0.0, m: 0.0, k: 1.0 }.to_rgb(), (0, 0, 0));
+    }
+
+    #[test]
+    fn try_from_hex_reports_bad_hex() {
+        assert_eq!(Color::try_from_hex("#ff8800"), Ok(Color::RGB { r: 255, g: 136, b: 0 }));
+        assert_eq!(Color::try_from_hex("f80"), Ok(Color::RGB { r: 255, g: 136, b: 0 }));
+        assert_eq!(Color::try_from_hex("#f808"), Ok(Color::RGBA { r: 255, g: 136, b: 0, a: 136 }));
+        assert_eq!(Color::try_from_hex("ff880080"), Ok(Color::RGBA { r: 255, g: 136, b: 0, a: 128 }));
+        assert!(Color::try_from_hex("#ff88zz").is_err());
+        assert!(Color::try_from_hex("#ff88000").is_err());
+        assert!(Color::try_from_hex("").is_err());
     }
 }
@@ -0,0 +1,122 @@
+use crate::style::flags::{BLINK, BOLD, CROSSED, DIM, HIDDEN, ITALIC, REVERSED, StyleFlag, UNDERLINE};
+use crate::style::Style;
+use crate::terminal::{Capabilities, ColorSupport, CAPABILITIES};
+
+/// Markers wrapped around text for each attribute when a [`PlainStyle`] falls back to plain text.
+///
+/// The default is markdown-ish: `*bold*`, `_italic_`, `__underline__`, and `~~crossed~~`. Blink,
+/// reversed, and dim have no marker. An empty marker leaves the attribute out.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlainMarkers {
+    pub bold: String,
+    pub italic: String,
+    pub underline: String,
+    pub crossed: String,
+    pub blink: String,
+    pub reversed: String,
+    pub dim: String,
+}
+
+impl Default for PlainMarkers {
+    fn default() -> Self {
+        PlainMarkers {
+            bold: "*".to_string(),
+            italic: "_".to_string(),
+            underline: "__".to_string(),
+            crossed: "~~".to_string(),
+            blink: String::new(),
+            reversed: String::new(),
+            dim: String::new(),
+        }
+    }
+}
+
+impl PlainMarkers {
+    fn marker(&self, flag: StyleFlag) -> &str {
+        match flag {
+            BOLD => &self.bold,
+            ITALIC => &self.italic,
+            UNDERLINE => &self.underline,
+            CROSSED => &self.crossed,
+            BLINK => &self.blink,
+            REVERSED => &self.reversed,
+            DIM => &self.dim,
+            _ => "",
+        }
+    }
+}
+
+/// Opt in renderer that keeps the emphasis of a style when the terminal has no color.
+///
+/// With color the text is styled as usual. With [`ColorSupport::None`], or no ansi support at
+/// all, the text is wrapped in [`PlainMarkers`] instead, Ex: bold `text` renders `*text*`.
+/// Hidden text is replaced with spaces since there is no way to conceal it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlainStyle {
+    style: Style,
+    markers: PlainMarkers,
+}
+
+impl PlainStyle {
+    pub fn new(style: Style) -> Self {
+        PlainStyle { style, markers: PlainMarkers::default() }
+    }
+
+    pub fn markers(mut self, markers: PlainMarkers) -> Self {
+        self.markers = markers;
+        self
+    }
+
+    /// Render the text for the terminal's [`CAPABILITIES`].
+    pub fn paint(&self, text: &str) -> String {
+        self.paint_for(text, &CAPABILITIES)
+    }
+
+    /// Render the text for the given capabilities.
+    pub fn paint_for(&self, text: &str, caps: &Capabilities) -> String {
+        let none = Style::default();
+        if caps.ansi && caps.color != ColorSupport::None {
+            return format!("{}{}{}", none.transition_for(&self.style, caps), text, self.style.transition_for(&none, caps));
+        }
+
+        // Markers nest, the first attribute opens first and closes last
+        let markers = self.style.flags.iter().map(|flag| self.markers.marker(flag)).collect::<Vec<_>>();
+        let mut plain = markers.concat();
+        if self.style.flags & HIDDEN == HIDDEN {
+            plain.extend(text.chars().map(|c| if c.is_whitespace() { c } else { ' ' }));
+        } else {
+            plain.push_str(text);
+        }
+        markers.iter().rev().for_each(|marker| plain.push_str(marker));
+        plain
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::style::Color;
+
+    const NONE: Capabilities = Capabilities { ansi: true, color: ColorSupport::None };
+
+    #[test]
+    fn bold_under_no_color_is_marked() {
+        let bold = PlainStyle::new(Style::builder().bold().fg(Color::RED));
+        assert_eq!(bold.paint_for("text", &NONE), "*text*");
+        assert_eq!(bold.paint_for("text", &Capabilities { ansi: false, color: ColorSupport::TrueColor }), "*text*");
+        assert_eq!(
+            bold.paint_for("text", &Capabilities { ansi: true, color: ColorSupport::Standard }),
+            "\x1b[1;31mtext\x1b[22;39m"
+        );
+    }
+
+    #[test]
+    fn markers_nest_and_can_be_replaced() {
+        let style = Style::builder().bold().italic().underline();
+        assert_eq!(PlainStyle::new(style.clone()).paint_for("x", &NONE), "*___x___*");
+
+        let markers = PlainMarkers { bold: "**".to_string(), italic: String::new(), ..PlainMarkers::default() };
+        assert_eq!(PlainStyle::new(style).markers(markers).paint_for("x", &NONE), "**__x__**");
+        assert_eq!(PlainStyle::new(Style::default()).paint_for("x", &NONE), "x");
+    }
+}
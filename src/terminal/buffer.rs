@@ -24,14 +24,244 @@
         HashSet with an index?
 */
 
-use std::collections::HashMap;
-use std::fmt::{Display, Formatter};
-use std::ops::{Bound, Range, RangeBounds, RangeFrom, RangeFull, RangeInclusive, RangeTo, RangeToInclusive};
-use crate::style::{AnsiSequence, color, Style};
+use std::collections::{BTreeSet, HashMap};
+use std::fmt::{Display, Formatter, Write};
+use std::iter;
+use std::ops::{Bound, Deref, DerefMut, Range, RangeBounds, RangeFrom, RangeFull, RangeInclusive, RangeTo, RangeToInclusive};
+use unicode_width::UnicodeWidthChar;
 
-struct Character {
+use crate::style::{AnsiSequence, Color, Style};
+use crate::terminal::ColorSupport;
+
+pub struct Character {
     style: Option<u64>,
     character: char,
+    /// Zero width characters, like combining marks, drawn in the same cell after the character
+    combining: String,
+    /// Rendered columns of the character
+    width: u8,
+    /// Part of the spaces a tab was expanded into
+    tab: bool,
+    /// Padding added by [`TerminalBuffer::set`] rather than written, shows the background pattern
+    empty: bool,
+    /// A wrap marker rather than pushed text
+    marker: bool,
+    animation: Option<Box<AnimatedCell>>,
+}
+
+impl Character {
+    fn new(character: char, style: Option<u64>) -> Self {
+        Character {
+            style,
+            character,
+            combining: String::new(),
+            width: char_width(character) as u8,
+            tab: false,
+            empty: false,
+            marker: false,
+            animation: None,
+        }
+    }
+
+    pub fn character(&self) -> char {
+        self.character
+    }
+
+    /// The zero width characters following the character in the same cell, Ex: the accent of
+    /// `e\u{301}`.
+    pub fn combining(&self) -> &str {
+        &self.combining
+    }
+
+    /// Number of rendered columns the character occupies, 2 for wide characters like CJK
+    /// ideographs and most emoji.
+    pub fn width(&self) -> usize {
+        self.width as usize
+    }
+
+    /// The frames of the cell if it is animated with [`TerminalBuffer::animate`].
+    pub fn animation(&self) -> Option<&AnimatedCell> {
+        self.animation.as_deref()
+    }
+}
+
+/// Frames of characters and styles a cell cycles through on [`TerminalBuffer::tick`].
+pub struct AnimatedCell {
+    /// Character and style key of each frame. Each style key is a reference to the style map
+    frames: Vec<(char, Option<u64>)>,
+    current: usize,
+}
+
+impl AnimatedCell {
+    /// Number of frames in the animation.
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    /// Index of the frame the cell is showing.
+    pub fn frame(&self) -> usize {
+        self.current
+    }
+}
+
+/// Number of columns a character occupies when rendered.
+fn char_width(c: char) -> usize {
+    c.width().unwrap_or(0)
+}
+
+/// Number of columns a line occupies when rendered.
+fn line_width(line: &[Character]) -> usize {
+    line.iter().map(Character::width).sum()
+}
+
+/// Map a range of rendered columns to a range of character indices in the line.
+///
+/// Wide characters that are partially in the range are included. An empty range maps to the
+/// index of the first character starting at or after `start`.
+fn column_range(line: &[Character], start: usize, end: usize) -> (usize, usize) {
+    let mut column = 0;
+    let mut first = line.len();
+    let mut last = line.len();
+    for (i, c) in line.iter().enumerate() {
+        let width = c.width();
+        if first == line.len() && column + width > start {
+            first = i;
+        }
+        if column >= end.max(start) {
+            last = i;
+            break;
+        }
+        column += width;
+    }
+
+    if start >= end {
+        (last, last)
+    } else {
+        (first.min(last), last)
+    }
+}
+
+/// Convert a line of text into characters starting at the given rendered column.
+///
+/// Tabs are expanded into spaces up to the next tab stop. Zero width characters are attached to
+/// the character before them, a zero width character at the start of the text is left on its own.
+fn to_characters(text: &str, style: Option<u64>, mut column: usize, tab_width: usize) -> Vec<Character> {
+    let mut characters: Vec<Character> = Vec::new();
+    for c in text.chars() {
+        if c == '\t' && tab_width > 0 {
+            let width = tab_width - (column % tab_width);
+            characters.extend((0..width).map(|_| Character { tab: true, ..Character::new(' ', style) }));
+            column += width;
+        } else if let Some(previous) = characters.last_mut().filter(|_| char_width(c) == 0) {
+            previous.combining.push(c);
+        } else {
+            characters.push(Character::new(c, style));
+            column += char_width(c);
+        }
+    }
+    characters
+}
+
+/// A run of characters sharing the same style in the json representation of a buffer.
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct Run {
+    text: String,
+    style: Option<Style>,
+}
+
+/// DECDHL/DECDWL attribute of a line, emitted before the line's content.
+///
+/// Every character of a double width or double height line takes up two columns of the terminal.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineSize {
+    #[default]
+    Normal,
+    /// `\x1b#6`
+    DoubleWidth,
+    /// Top half of a double height line, `\x1b#3`
+    DoubleHeightTop,
+    /// Bottom half of a double height line, `\x1b#4`
+    DoubleHeightBottom,
+}
+
+impl LineSize {
+    /// Sequence that sets the attribute of the line the cursor is on.
+    pub fn sequence(&self) -> &'static str {
+        match self {
+            LineSize::Normal => "",
+            LineSize::DoubleWidth => "\x1b#6",
+            LineSize::DoubleHeightTop => "\x1b#3",
+            LineSize::DoubleHeightBottom => "\x1b#4",
+        }
+    }
+
+    /// Terminal columns taken up by each column of the line.
+    fn scale(&self) -> usize {
+        match self {
+            LineSize::Normal => 1,
+            _ => 2,
+        }
+    }
+}
+
+/// Direction the characters of a line are displayed in.
+///
+/// Characters are always stored, pushed, and addressed by column in logical order. Only the
+/// rendered order changes, so the first character of a right to left line is drawn rightmost.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    #[default]
+    Ltr,
+    Rtl,
+}
+
+/// The characters of a line, its size, and its direction.
+#[derive(Default)]
+struct Line {
+    characters: Vec<Character>,
+    size: LineSize,
+    direction: Direction,
+    /// Continues the previous line after it was wrapped
+    wrapped: bool,
+    /// The space the previous line was wrapped at was dropped from the end of it
+    dropped_space: bool,
+}
+
+impl From<Vec<Character>> for Line {
+    fn from(characters: Vec<Character>) -> Self {
+        Line { characters, size: LineSize::Normal, direction: Direction::Ltr, wrapped: false, dropped_space: false }
+    }
+}
+
+/// Marker drawn where pushed text is wrapped, see [`TerminalBuffer::set_wrap_marker`].
+///
+/// The marker takes up columns like any other character, so it should be a single column
+/// character.
+#[derive(Debug, Clone, PartialEq)]
+pub enum WrapMarker {
+    /// Drawn at the start of every continuation line, Ex: `↪`
+    Continuation(char, Style),
+    /// Drawn at the end of a line where it was broken, Ex: `\`
+    Break(char, Style),
+}
+
+impl Deref for Line {
+    type Target = Vec<Character>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.characters
+    }
+}
+
+impl DerefMut for Line {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.characters
+    }
 }
 
 struct MappedStyle {
@@ -53,12 +283,139 @@ impl MappedStyle {
     }
 }
 
-struct TerminalBuffer {
-    buffer: Vec<Vec<Character>>,
-    styles: HashMap<u64, MappedStyle>
+pub struct TerminalBuffer {
+    buffer: Vec<Line>,
+    styles: HashMap<u64, MappedStyle>,
+    tab_width: usize,
+    /// Inclusive top and bottom lines of the scroll region
+    scroll_region: Option<(usize, usize)>,
+    /// Rows and columns of a buffer created with `with_capacity`
+    capacity: Option<(usize, usize)>,
+    overflow: Overflow,
+    /// Rendered columns pushed text is word wrapped at
+    wrap_width: Option<usize>,
+    wrap_marker: Option<WrapMarker>,
+    /// Line and rendered column of the cells changed since the last `take_dirty`
+    dirty: BTreeSet<(usize, usize)>,
+    /// Rows of characters tiled behind the unstyled spaces when rendering
+    background: Vec<Vec<char>>,
+    cursor: Cursor,
+}
+
+/// Shape of the terminal cursor, set with DECSCUSR.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CursorShape {
+    #[default]
+    Block,
+    Underline,
+    Bar,
+}
+
+impl CursorShape {
+    /// Sequence that sets the steady, not blinking, shape of the cursor.
+    pub fn sequence(&self) -> &'static str {
+        match self {
+            CursorShape::Block => "\x1b[2 q",
+            CursorShape::Underline => "\x1b[4 q",
+            CursorShape::Bar => "\x1b[6 q",
+        }
+    }
+}
+
+/// Position, in lines and rendered columns, visibility, and shape of the cursor of a buffer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cursor {
+    pub line: usize,
+    pub column: usize,
+    pub visible: bool,
+    pub shape: CursorShape,
+}
+
+impl Default for Cursor {
+    fn default() -> Self {
+        Cursor { line: 0, column: 0, visible: true, shape: CursorShape::Block }
+    }
+}
+
+/// What happens when pushed, set, or replaced text goes past the rows or columns of a buffer
+/// created with [`TerminalBuffer::with_capacity`].
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Overflow {
+    /// The buffer grows past its capacity. The capacity only preallocates.
+    #[default]
+    Grow,
+    /// Text past the last column wraps onto the next line and lines past the last row scroll
+    /// the top line off of the buffer.
+    Wrap,
+    /// Writing text that doesn't fit is an error and the buffer is left unchanged.
+    Error,
+}
+
+/// Error when written text doesn't fit a buffer with [`Overflow::Error`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OverflowError {
+    /// A line would be wider than the buffer's columns
+    Columns { width: usize, columns: usize },
+    /// The buffer would have more lines than its rows
+    Rows { lines: usize, rows: usize },
+}
+
+impl Display for OverflowError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OverflowError::Columns { width, columns } => {
+                write!(f, "line width of {} is more than the {} columns of the buffer", width, columns)
+            }
+            OverflowError::Rows { lines, rows } => write!(f, "{} lines is more than the {} rows of the buffer", lines, rows),
+        }
+    }
+}
+
+impl std::error::Error for OverflowError {}
+
+/// Error when the ranges of [`TerminalBuffer::replace`] don't fit the buffer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReplaceError {
+    /// The line range starts after the last line or ends before it starts
+    Lines { start: usize, end: usize, lines: usize },
+    /// The column range starts past the end of the first line or ends before it starts
+    Columns { start: usize, end: usize, width: usize },
+    /// The replaced text doesn't fit a buffer with [`Overflow::Error`]
+    Overflow(OverflowError),
+}
+
+impl Display for ReplaceError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ReplaceError::Lines { start, end, lines } => {
+                write!(f, "line range {}..{} is invalid for a buffer of {} lines", start, end, lines)
+            }
+            ReplaceError::Columns { start, end, width } => {
+                write!(f, "column range {}..{} is invalid for a line of width {}", start, end, width)
+            }
+            ReplaceError::Overflow(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for ReplaceError {}
+
+/// Error when the top of a [`TerminalBuffer::set_scroll_region`] is below its bottom.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScrollRegionError {
+    pub top: usize,
+    pub bottom: usize,
+}
+
+impl Display for ScrollRegionError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "scroll region {}..={} has its top below its bottom", self.top, self.bottom)
+    }
 }
 
-trait ReplaceRange {
+impl std::error::Error for ScrollRegionError {}
+
+pub trait ReplaceRange {
     /// Inclusive lower bound
     fn start(&self) -> usize;
     /// Exclusive upper bound
@@ -73,7 +430,7 @@ impl ReplaceRange for usize {
         *self
     }
     fn end(&self) -> usize {
-        self + 1
+        self.saturating_add(1)
     }
 }
 impl ReplaceRange for RangeFull {
@@ -113,126 +470,1933 @@ impl ReplaceRange for RangeToInclusive<usize> {
         0
     }
     fn end(&self) -> usize {
-        self.end + 1
+        self.end.saturating_add(1)
     }
 }
 impl ReplaceRange for RangeInclusive<usize> {
     fn start(&self) -> usize {
-        if let Bound::Included(val) = self.start_bound() { *val }
-        else { 0 }
+        match self.start_bound() {
+            Bound::Included(val) => *val,
+            Bound::Excluded(val) => val.saturating_add(1),
+            Bound::Unbounded => 0,
+        }
     }
     fn end(&self) -> usize {
-        if let Bound::Included(val) = self.end_bound() { *val }
-        else { 0 }
+        // An exhausted range excludes its end
+        match self.end_bound() {
+            Bound::Included(val) => val.saturating_add(1),
+            Bound::Excluded(val) => *val,
+            Bound::Unbounded => usize::MAX,
+        }
     }
 }
 
 
 
+/// Resolve a `RangeBounds` into an exclusive `start..end` pair clamped to `max`
+fn clamp_range<R: RangeBounds<usize>>(range: &R, max: usize) -> (usize, usize) {
+    let start = match range.start_bound() {
+        Bound::Included(val) => *val,
+        Bound::Excluded(val) => val.saturating_add(1),
+        Bound::Unbounded => 0,
+    };
+    let end = match range.end_bound() {
+        Bound::Included(val) => val.saturating_add(1),
+        Bound::Excluded(val) => *val,
+        Bound::Unbounded => max,
+    };
+    (start.min(max), end.min(max))
+}
+
+/// Sink that only counts the bytes written to it.
+struct ByteCounter(usize);
+
+impl Write for ByteCounter {
+    fn write_str(&mut self, s: &str) -> std::fmt::Result {
+        self.0 += s.len();
+        Ok(())
+    }
+}
+
+/// Quantize the color at `x` with the error diffused to it, then diffuse the new error to the
+/// neighboring cells using the Floyd–Steinberg weights.
+///
+/// `current` and `next` are the errors of this line and the next line offset by one so the
+/// left neighbor of the first cell has an index.
+fn diffuse(color: Color, x: usize, current: &mut [[f32; 3]], next: &mut [[f32; 3]], support: ColorSupport) -> Option<Color> {
+    if color == Color::Default {
+        return color.downsample(support);
+    }
+
+    let (r, g, b) = color.to_rgb();
+    let wanted = [
+        (r as f32 + current[x][0]).clamp(0.0, 255.0),
+        (g as f32 + current[x][1]).clamp(0.0, 255.0),
+        (b as f32 + current[x][2]).clamp(0.0, 255.0),
+    ];
+    let quantized = Color::RGB {
+        r: wanted[0].round() as u8,
+        g: wanted[1].round() as u8,
+        b: wanted[2].round() as u8,
+    }
+    .downsample(support)?;
+
+    let (qr, qg, qb) = quantized.to_rgb();
+    let error = [wanted[0] - qr as f32, wanted[1] - qg as f32, wanted[2] - qb as f32];
+    for channel in 0..3 {
+        current[x + 1][channel] += error[channel] * 7.0 / 16.0;
+        next[x - 1][channel] += error[channel] * 3.0 / 16.0;
+        next[x][channel] += error[channel] * 5.0 / 16.0;
+        next[x + 1][channel] += error[channel] / 16.0;
+    }
+    Some(quantized)
+}
+
+impl Default for TerminalBuffer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl TerminalBuffer {
-    fn new() -> Self {
+    pub fn new() -> Self {
         TerminalBuffer {
-            buffer: vec![Vec::new()],
-            styles: HashMap::new()
+            buffer: vec![Line::default()],
+            styles: HashMap::new(),
+            tab_width: 8,
+            scroll_region: None,
+            capacity: None,
+            overflow: Overflow::Grow,
+            wrap_width: None,
+            wrap_marker: None,
+            dirty: BTreeSet::new(),
+            background: Vec::new(),
+            cursor: Cursor::default(),
+        }
+    }
+
+    /// Create a buffer with room for the rows and columns.
+    ///
+    /// The buffer still grows past its capacity unless an [`Overflow`] policy is set with
+    /// [`TerminalBuffer::set_overflow`].
+    pub fn with_capacity(rows: usize, columns: usize) -> Self {
+        let mut buffer = TerminalBuffer::new();
+        buffer.buffer = Vec::with_capacity(rows);
+        buffer.buffer.push(Line::from(Vec::with_capacity(columns)));
+        buffer.capacity = Some((rows, columns));
+        buffer
+    }
+
+    /// Set what happens when pushed text goes past the capacity of the buffer. Has no effect on
+    /// buffers that weren't created with [`TerminalBuffer::with_capacity`].
+    pub fn set_overflow(&mut self, overflow: Overflow) {
+        self.overflow = overflow;
+    }
+
+    /// Wrap pushed text onto the next line before it goes past the rendered columns, `None` to
+    /// stop wrapping.
+    ///
+    /// Lines are broken after the last space so words move to the next line as a whole, words
+    /// wider than the width are broken anywhere. A space that would go past the width is dropped
+    /// at the break instead of starting the next line. Wide characters are never split. Takes the place
+    /// of the columns of [`Overflow::Wrap`] and only affects text pushed after the change.
+    pub fn set_wrap_width(&mut self, width: Option<usize>) {
+        self.wrap_width = width;
+    }
+
+    /// Draw a marker where pushed text is wrapped, either with [`TerminalBuffer::set_wrap_width`]
+    /// or [`Overflow::Wrap`], `None` to stop drawing markers.
+    ///
+    /// Markers are part of the lines they are drawn on but not of the text,
+    /// [`TerminalBuffer::to_plain`] leaves them out. Only affects text pushed after the change.
+    pub fn set_wrap_marker(&mut self, marker: Option<WrapMarker>) {
+        self.wrap_marker = marker;
+    }
+
+    /// Set the distance between tab stops used when expanding tabs, defaults to 8.
+    ///
+    /// Only affects text added after the change. A width of 0 keeps tabs as is.
+    pub fn set_tab_width(&mut self, width: usize) {
+        self.tab_width = width;
+    }
+
+    /// Add references to the style, inserting it into the style map if it isn't already present.
+    /// Returns the key that characters should reference.
+    fn retain_style(&mut self, style: Style, count: usize) -> u64 {
+        let key = style.hash_key();
+        if count > 0 {
+            let mapped = self.styles.entry(key).or_insert(MappedStyle { style, refs: 0 });
+            for _ in 0..count {
+                mapped.increment();
+            }
         }
+        key
     }
 
-    fn push<D: Display>(&mut self, chunk: D) {
-        let mut last = self.buffer.last_mut().unwrap();
-        for c in chunk.to_string().chars() {
-            if c == '\n' {
-                self.buffer.push(Vec::new());
-                last = self.buffer.last_mut().unwrap();
-            } else {
-                last.push(Character { style: None, character: c });
+    /// Remove a reference to the style, evicting it from the style map when nothing references it.
+    fn release_style(&mut self, key: u64) {
+        if let Some(mapped) = self.styles.get_mut(&key) {
+            if mapped.decrement() {
+                self.styles.remove(&key);
             }
         }
     }
 
-    fn push_styled<D: Display>(&mut self, style: Style, chunk: D) {
-        let key = style.hash_key();
-        if self.styles.contains_key(&key) {
-            self.styles.get_mut(&key).unwrap().increment();
-        } else {
-            self.styles.insert(key, MappedStyle { style, refs: 1 });
+    /// Set the inclusive top and bottom lines of the scroll region, similar to DECSTBM.
+    ///
+    /// Once the buffer reaches the bottom of the region, pushed text is written to the bottom
+    /// line of the region. Pushing a new line then scrolls only the lines in the region up,
+    /// discarding the top line of the region. Lines outside the region stay fixed.
+    pub fn set_scroll_region(&mut self, top: usize, bottom: usize) -> Result<(), ScrollRegionError> {
+        if top > bottom {
+            return Err(ScrollRegionError { top, bottom });
+        }
+        self.scroll_region = Some((top, bottom));
+        Ok(())
+    }
+
+    /// Remove the scroll region so the whole buffer grows when pushing new lines.
+    pub fn clear_scroll_region(&mut self) {
+        self.scroll_region = None;
+    }
+
+    pub fn cursor(&self) -> Cursor {
+        self.cursor
+    }
+
+    /// Move the cursor to the line and rendered column, clamped to the last line and to the end
+    /// of the line.
+    ///
+    /// The cursor is clamped again whenever the buffer shrinks under it, Ex: after
+    /// [`TerminalBuffer::clear`] or [`TerminalBuffer::replace`].
+    pub fn move_to(&mut self, line: usize, column: usize) {
+        let line = line.min(self.buffer.len() - 1);
+        self.cursor.line = line;
+        self.cursor.column = column.min(line_width(&self.buffer[line]));
+    }
+
+    /// Move the cursor by a number of lines and logical columns, saturating at the bounds of the
+    /// buffer the same as [`TerminalBuffer::move_to`].
+    ///
+    /// Columns are counted with [`TerminalBuffer::logical_columns`], so moving past a tab jumps to
+    /// the next tab stop and moving past a wide character skips all of its columns.
+    pub fn move_by(&mut self, lines: isize, columns: isize) {
+        self.move_to(self.cursor.line.saturating_add_signed(lines), self.cursor.column);
+        let line = self.cursor.line;
+        let mut column = self.cursor.column;
+        for _ in 0..columns.unsigned_abs() {
+            column = match columns > 0 {
+                true => self.next_column(line, column),
+                false => self.prev_column(line, column),
+            };
+        }
+        self.move_to(line, column);
+    }
+
+    /// Clamp the cursor to the buffer after lines or characters were removed.
+    fn clamp_cursor(&mut self) {
+        self.move_to(self.cursor.line, self.cursor.column);
+    }
+
+    pub fn hide(&mut self) {
+        self.cursor.visible = false;
+    }
+
+    pub fn show(&mut self) {
+        self.cursor.visible = true;
+    }
+
+    pub fn set_shape(&mut self, shape: CursorShape) {
+        self.cursor.shape = shape;
+    }
+
+    /// The line that pushed text is written to.
+    fn write_line(&self) -> usize {
+        match self.scroll_region {
+            Some((_, bottom)) if self.buffer.len() > bottom => bottom,
+            _ => self.buffer.len() - 1,
+        }
+    }
+
+    /// Start a new line, scrolling the scroll region if the bottom of it is reached.
+    ///
+    /// With [`Overflow::Wrap`] the top line is scrolled off when the buffer is past its rows.
+    fn new_line(&mut self) {
+        match self.scroll_region {
+            Some((top, bottom)) if self.buffer.len() > bottom => {
+                let before = self.widths(top..bottom + 1);
+                let removed = self.buffer.remove(top);
+                self.release_characters(removed.characters);
+                self.buffer.insert(bottom, Line::default());
+                self.mark_lines(top, before);
+            }
+            _ => self.buffer.push(Line::default()),
         }
 
-        let mut last = self.buffer.last_mut().unwrap();
-        for c in chunk.to_string().chars() {
-            if c == '\n' {
-                self.buffer.push(Vec::new());
-                last = self.buffer.last_mut().unwrap();
-            } else {
-                last.push(Character { style: Some(key), character: c });
+        if let (Some((rows, _)), Overflow::Wrap) = (self.capacity, self.overflow) {
+            if self.buffer.len() > rows.max(1) {
+                let before = self.widths(0..self.buffer.len());
+                let removed = self.buffer.remove(0);
+                self.release_characters(removed.characters);
+                self.mark_lines(0, before);
             }
         }
     }
 
-    /// Starting at the given line and column replace text until end of given chunk
-    fn replace<D: Display, R1: ReplaceRange, R2: ReplaceRange>(&mut self, mut lines: R1, mut columns: R2, chunk: D) {
-        if lines.start() >= self.buffer.len() || lines.end() >= self.buffer.len() {
-            panic!("Line range is out of bounds: {}..{}", lines.start(), lines.end());
+    /// Make sure the lines of characters fit in the buffer when they are pushed.
+    ///
+    /// The first line is appended to the current line so it takes up the current line's size.
+    fn check_fits(&self, lines: &[Vec<Character>]) -> Result<(), OverflowError> {
+        let Some((rows, columns)) = self.capacity else {
+            return Ok(());
+        };
+        for (i, characters) in lines.iter().enumerate() {
+            let width = match i {
+                0 => {
+                    let line = &self.buffer[self.write_line()];
+                    (line_width(line) + line_width(characters)) * line.size.scale()
+                }
+                _ => line_width(characters),
+            };
+            if width > columns {
+                return Err(OverflowError::Columns { width, columns });
+            }
         }
-        if lines.start() > lines.end() {
-            panic!("Invalid line range: {}..{}", lines.start(), lines.end());
+        let total = self.buffer.len() + lines.len() - 1;
+        if self.scroll_region.is_none() && total > rows {
+            return Err(OverflowError::Rows { lines: total, rows });
         }
-        if columns.start() >= self.buffer[lines.start()].len() {
-            panic!("Column range is out of bounds: {}..{}", columns.start(), columns.end());
+        Ok(())
+    }
+
+    /// Make sure lines of the rendered widths, already scaled by their line size, fit in the
+    /// columns and a buffer of `lines` lines fits in the rows.
+    fn check_size<I: IntoIterator<Item = usize>>(&self, widths: I, lines: usize) -> Result<(), OverflowError> {
+        let Some((rows, columns)) = self.capacity else {
+            return Ok(());
+        };
+        if let Some(width) = widths.into_iter().find(|width| *width > columns) {
+            return Err(OverflowError::Columns { width, columns });
         }
-        if columns.start() > columns.end() {
-            panic!("Invalid column range: {}..{}", columns.start(), columns.end());
+        if lines > rows {
+            return Err(OverflowError::Rows { lines, rows });
         }
+        Ok(())
+    }
 
-        // TODO: Join last line with end line at end column
-        /*
-            From startline & startcolumn to endline & endcolumn. Delete content and inject content
-            in the gap that it leaves working to merge lines where possible.
+    /// Wrap the lines from `first` on that are wider than the columns onto new lines, then scroll
+    /// the top lines off the buffer past its rows. Used by [`Overflow::Wrap`] for text that is
+    /// written in place rather than pushed, so no wrap marker is drawn.
+    fn wrap_to_capacity(&mut self, first: usize) {
+        let Some((rows, columns)) = self.capacity else {
+            return;
+        };
+        let before = self.widths(first..self.buffer.len());
+        let mut line = first;
+        while line < self.buffer.len() {
+            let scale = self.buffer[line].size.scale();
+            let mut width = 0;
+            let split = self.buffer[line].iter().position(|c| {
+                width += c.width() * scale;
+                width > columns
+            });
+            // A character wider than the columns stays on its own line
+            if let Some(index) = split.filter(|index| *index > 0) {
+                let rest = self.buffer[line].split_off(index);
+                let next = Line { wrapped: true, size: self.buffer[line].size, ..Line::from(rest) };
+                self.buffer.insert(line + 1, next);
+            }
+            line += 1;
+        }
+        let lines = before.len().max(self.buffer.len() - first);
+        self.mark_lines(first, before.into_iter().chain(iter::repeat(0)).take(lines).collect());
 
-            1. Convert replacement text to pseudo buffer
-            2. Merge first chunk last line with new first line
-            3. Merge new last line with last chunk first line
-        */
+        let rows = rows.max(1);
+        if self.buffer.len() > rows {
+            let before = self.widths(0..self.buffer.len());
+            let removed = self.buffer.drain(..self.buffer.len() - rows).flat_map(|l| l.characters).collect::<Vec<_>>();
+            self.release_characters(removed);
+            self.mark_lines(0, before);
+        }
+    }
+
+    /// Push a chunk of text where every character references the given style.
+    fn push_chunk(&mut self, chunk: &str, style: Option<&Style>) -> Result<(), OverflowError> {
+        let key = style.map(|s| s.hash_key());
+        let mut lines = chunk
+            .split('\n')
+            .enumerate()
+            .map(|(i, text)| {
+                let column = if i == 0 { line_width(&self.buffer[self.write_line()]) } else { 0 };
+                to_characters(text, key, column, self.tab_width)
+            })
+            .collect::<Vec<_>>();
+        if self.overflow == Overflow::Error {
+            self.check_fits(&lines)?;
+        }
+        self.attach_combining(self.write_line(), &mut lines[0]);
+
+        // Only the wrap width breaks at words, the capacity breaks anywhere
+        let wrap = match (self.wrap_width, self.capacity, self.overflow) {
+            (Some(width), _, _) => Some((width, true)),
+            (None, Some((_, columns)), Overflow::Wrap) => Some((columns, false)),
+            _ => None,
+        };
+        for (i, characters) in lines.into_iter().enumerate() {
+            if i > 0 {
+                self.new_line();
+            }
+            if let Some(style) = style {
+                self.retain_style(style.clone(), characters.len());
+            }
+            match wrap {
+                Some((columns, words)) => {
+                    // Widths are in terminal columns so characters of double width lines count twice
+                    let mut line = self.write_line();
+                    let mut column = line_width(&self.buffer[line]);
+                    let mut width = column * self.buffer[line].size.scale();
+                    // A marker at the break always needs room at the end of the line
+                    let (reserved, continuation) = match &self.wrap_marker {
+                        Some(WrapMarker::Break(ch, _)) => (char_width(*ch), 0),
+                        Some(WrapMarker::Continuation(ch, _)) => (0, char_width(*ch)),
+                        None => (0, 0),
+                    };
+                    for character in characters {
+                        let char_width = character.width();
+                        let limit = |line: &Line| columns.saturating_sub(reserved * line.size.scale());
+                        if width + char_width * self.buffer[line].size.scale() > limit(&self.buffer[line]) && width > 0 {
+                            // A space that doesn't fit breaks the line and is dropped at the break
+                            if words && character.character == ' ' {
+                                line = self.wrap_line(line, Vec::new());
+                                column = line_width(&self.buffer[line]);
+                                width = column * self.buffer[line].size.scale();
+                                self.buffer[line].dropped_space = true;
+                                self.release_characters([character]);
+                                continue;
+                            }
+                            let word = match words {
+                                true => {
+                                    let room = columns.saturating_sub(reserved + continuation + char_width);
+                                    self.split_last_word(line, room)
+                                }
+                                false => Vec::new(),
+                            };
+                            line = self.wrap_line(line, word);
+                            column = line_width(&self.buffer[line]);
+                            width = column * self.buffer[line].size.scale();
 
-        println!("{}..{} | {}..{}", lines.start(), lines.end(), columns.start(), columns.end());
+                            // The character still doesn't fit after the start of its word
+                            if width + char_width * self.buffer[line].size.scale() > limit(&self.buffer[line])
+                                && self.buffer[line].iter().any(|c| !c.marker)
+                            {
+                                line = self.wrap_line(line, Vec::new());
+                                column = line_width(&self.buffer[line]);
+                                width = column * self.buffer[line].size.scale();
+                            }
+                        }
+                        width += char_width * self.buffer[line].size.scale();
+                        self.buffer[line].push(character);
+                        self.mark(line, column..column + char_width);
+                        column += char_width;
+                    }
+                }
+                None => {
+                    let line = self.write_line();
+                    let start = line_width(&self.buffer[line]);
+                    self.buffer[line].extend(characters);
+                    self.mark(line, start..line_width(&self.buffer[line]));
+                }
+            }
+        }
+        Ok(())
     }
-}
 
-impl Display for TerminalBuffer {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        let mut buffer = Vec::new();
+    /// Continue wrapped text on a new line, drawing the wrap marker and moving the word that
+    /// didn't fit onto the new line. Returns the new line.
+    fn wrap_line(&mut self, line: usize, word: Vec<Character>) -> usize {
+        if let Some(WrapMarker::Break(ch, style)) = self.wrap_marker.clone() {
+            let start = line_width(&self.buffer[line]);
+            let marker = self.marker(ch, style);
+            self.buffer[line].push(marker);
+            self.mark(line, start..start + char_width(ch));
+        }
+
+        self.new_line();
+        let line = self.write_line();
+        self.buffer[line].wrapped = true;
+        if let Some(WrapMarker::Continuation(ch, style)) = self.wrap_marker.clone() {
+            let marker = self.marker(ch, style);
+            self.buffer[line].push(marker);
+        }
+        self.buffer[line].extend(word);
+        self.mark(line, 0..line_width(&self.buffer[line]));
+        line
+    }
+
+    /// A wrap marker cell holding a reference to its style.
+    fn marker(&mut self, ch: char, style: Style) -> Character {
+        let key = (style != Style::default()).then(|| self.retain_style(style, 1));
+        Character { marker: true, ..Character::new(ch, key) }
+    }
+
+    /// Split off the characters after the last space of the line, so a wrapped word moves to the
+    /// next line as a whole. Empty when the line has no space, ends with one, or the word is
+    /// wider than `room` and would have to be broken anyway.
+    fn split_last_word(&mut self, line: usize, room: usize) -> Vec<Character> {
+        let characters = &self.buffer[line];
+        match characters.iter().rposition(|c| c.character == ' ') {
+            Some(index) if index + 1 < characters.len() && line_width(&characters[index + 1..]) <= room => {
+                let columns = line_width(&characters[..index + 1])..line_width(characters);
+                let word = self.buffer[line].split_off(index + 1);
+                self.mark(line, columns);
+                word
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    /// Attach the zero width characters at the start of converted text to the last character of
+    /// the line they are added to.
+    fn attach_combining(&mut self, line: usize, characters: &mut Vec<Character>) {
+        let count = characters.iter().take_while(|c| c.width == 0).count();
+        let width = line_width(&self.buffer[line]);
+        if count == 0 || self.buffer[line].is_empty() {
+            return;
+        }
+        let last = self.buffer[line].last_mut().unwrap();
+        let columns = width - last.width()..width;
+        for character in characters.drain(..count) {
+            last.combining.push(character.character);
+            last.combining.push_str(&character.combining);
+        }
+        self.mark(line, columns);
+    }
+
+    /// Push a chunk of text onto the end of the buffer.
+    ///
+    /// # Panics
+    /// If the buffer has [`Overflow::Error`] and the text doesn't fit. Use
+    /// [`TerminalBuffer::try_push`] to handle the error instead.
+    pub fn push<D: Display>(&mut self, chunk: D) {
+        if let Err(error) = self.push_chunk(&chunk.to_string(), None) {
+            panic!("{}", error);
+        }
+    }
+
+    /// Same as [`TerminalBuffer::push`] but returns an error if the text doesn't fit.
+    pub fn try_push<D: Display>(&mut self, chunk: D) -> Result<(), OverflowError> {
+        self.push_chunk(&chunk.to_string(), None)
+    }
+
+    /// Push a chunk of text where every character references the given style.
+    ///
+    /// Each character holds its own reference to the style.
+    ///
+    /// # Panics
+    /// If the buffer has [`Overflow::Error`] and the text doesn't fit. Use
+    /// [`TerminalBuffer::try_push_styled`] to handle the error instead.
+    pub fn push_styled<D: Display>(&mut self, style: Style, chunk: D) {
+        if let Err(error) = self.push_chunk(&chunk.to_string(), Some(&style)) {
+            panic!("{}", error);
+        }
+    }
+
+    /// Same as [`TerminalBuffer::push_styled`] but returns an error if the text doesn't fit.
+    pub fn try_push_styled<D: Display>(&mut self, style: Style, chunk: D) -> Result<(), OverflowError> {
+        self.push_chunk(&chunk.to_string(), Some(&style))
+    }
+
+    /// The rendered column that each logical column of the line starts at.
+    ///
+    /// The spaces an expanded tab occupies are a single logical column and wide characters are
+    /// a single logical column spanning multiple rendered columns. [`TerminalBuffer::move_by`]
+    /// moves the cursor by these columns.
+    pub fn logical_columns(&self, line: usize) -> Vec<usize> {
+        let tab_width = self.tab_width.max(1);
+        let mut columns = Vec::new();
+        let mut column = 0;
+        let mut previous_tab = false;
+        for c in self.buffer.get(line).into_iter().flat_map(|l| l.iter()) {
+            let width = c.width();
+            if width > 0 && (!c.tab || !previous_tab || column % tab_width == 0) {
+                columns.push(column);
+            }
+            previous_tab = c.tab;
+            column += width;
+        }
+        columns
+    }
+
+    /// The rendered column of the next logical column after `column`, jumping to the next tab
+    /// stop when moving past a tab. The end of the line is the last column.
+    pub fn next_column(&self, line: usize, column: usize) -> usize {
+        let len = self.buffer.get(line).map_or(0, |l| line_width(l));
+        self.logical_columns(line)
+            .into_iter()
+            .find(|col| *col > column)
+            .unwrap_or(len)
+    }
+
+    /// The rendered column of the logical column before `column`, jumping back to the start of
+    /// a tab when moving past a tab.
+    pub fn prev_column(&self, line: usize, column: usize) -> usize {
+        self.logical_columns(line)
+            .into_iter()
+            .rev()
+            .find(|col| *col < column)
+            .unwrap_or(0)
+    }
+
+    /// Change the style of the existing characters in the rectangle of `lines` x `columns`
+    /// without changing the characters themselves.
+    ///
+    /// Columns are rendered columns so wide characters span multiple columns. Ranges are clamped
+    /// to the buffer and each line's width. Restyling with `Style::default()` clears the styling
+    /// of the characters.
+    pub fn restyle<R1: RangeBounds<usize>, R2: RangeBounds<usize>>(&mut self, lines: R1, columns: R2, style: Style) {
+        let (line_start, line_end) = clamp_range(&lines, self.buffer.len());
+        let unstyled = style == Style::default();
+
+        for line in line_start..line_end {
+            let (col_start, col_end) = clamp_range(&columns, line_width(&self.buffer[line]));
+            let (col_start, col_end) = column_range(&self.buffer[line], col_start, col_end);
+            let first_column = line_width(&self.buffer[line][..col_start]);
+            self.mark(line, first_column..first_column + line_width(&self.buffer[line][col_start..col_end]));
+            for col in col_start..col_end {
+                let new = if unstyled { None } else { Some(self.retain_style(style.clone(), 1)) };
+                if let Some(old) = std::mem::replace(&mut self.buffer[line][col].style, new) {
+                    self.release_style(old);
+                }
+            }
+        }
+    }
+
+    /// Fill the background of the rectangle of `lines` x `columns` with a bilinear gradient
+    /// between the colors of its four corners, Ex: for headers and panels.
+    ///
+    /// The rectangle is clamped to the buffer and the corners are its first and last lines and
+    /// rendered columns. Only existing cells are filled and the rest of their style is kept.
+    pub fn gradient_fill<R1: RangeBounds<usize>, R2: RangeBounds<usize>>(
+        &mut self,
+        lines: R1,
+        columns: R2,
+        top_left: Color,
+        top_right: Color,
+        bottom_left: Color,
+        bottom_right: Color,
+    ) {
+        let (line_start, line_end) = clamp_range(&lines, self.buffer.len());
+        let max_width = self.buffer[line_start..line_end].iter().map(|l| line_width(l)).max().unwrap_or(0);
+        let (col_start, col_end) = clamp_range(&columns, max_width);
+        let ratio = |value: usize, start: usize, end: usize| match end.saturating_sub(start) {
+            0 | 1 => 0.0,
+            span => (value - start) as f32 / (span - 1) as f32,
+        };
+
+        for line in line_start..line_end {
+            let v = ratio(line, line_start, line_end);
+            let mut column = 0;
+            for index in 0..self.buffer[line].len() {
+                let start = column;
+                column += self.buffer[line][index].width();
+                if start < col_start || start >= col_end {
+                    continue;
+                }
+
+                let u = ratio(start, col_start, col_end);
+                let bg = top_left.blend(&top_right, u).blend(&bottom_left.blend(&bottom_right, u), v);
+                let style = self.style_of(&self.buffer[line][index]).cloned().unwrap_or_default();
+                let filled = Style { bg: Some(bg), ..style.clone() };
+                if filled != style {
+                    self.mark(line, start..column);
+                    let new = Some(self.retain_style(filled, 1));
+                    if let Some(old) = std::mem::replace(&mut self.buffer[line][index].style, new) {
+                        self.release_style(old);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Downsample the foreground and background colors in the rectangle of `lines` x `columns`
+    /// to the color support using Floyd–Steinberg dithering.
+    ///
+    /// The quantization error of each cell is spread to its unvisited neighbors, so gradients,
+    /// like images rendered with background colors, keep their overall tone instead of banding
+    /// into the nearest colors. Columns are rendered columns and unstyled cells are skipped.
+    pub fn dither_region<R1: RangeBounds<usize>, R2: RangeBounds<usize>>(&mut self, lines: R1, columns: R2, support: ColorSupport) {
+        let (line_start, line_end) = clamp_range(&lines, self.buffer.len());
+        let max_width = self.buffer[line_start..line_end].iter().map(|l| line_width(l)).max().unwrap_or(0);
+        let (col_start, col_end) = clamp_range(&columns, max_width);
+        let span = col_end.saturating_sub(col_start) + 2;
+
+        let (mut fg_current, mut fg_next) = (vec![[0.0; 3]; span], vec![[0.0; 3]; span]);
+        let (mut bg_current, mut bg_next) = (vec![[0.0; 3]; span], vec![[0.0; 3]; span]);
+        for line in line_start..line_end {
+            let mut column = 0;
+            for index in 0..self.buffer[line].len() {
+                let start = column;
+                column += self.buffer[line][index].width();
+                if start < col_start || start >= col_end {
+                    continue;
+                }
+                let Some(style) = self.style_of(&self.buffer[line][index]).cloned() else {
+                    continue;
+                };
+
+                let x = start - col_start + 1;
+                let dithered = Style {
+                    fg: style.fg.and_then(|c| diffuse(c, x, &mut fg_current, &mut fg_next, support)),
+                    bg: style.bg.and_then(|c| diffuse(c, x, &mut bg_current, &mut bg_next, support)),
+                    ..style.clone()
+                };
+                if dithered != style {
+                    self.mark(line, start..column);
+                    let new = (dithered != Style::default()).then(|| self.retain_style(dithered, 1));
+                    if let Some(old) = std::mem::replace(&mut self.buffer[line][index].style, new) {
+                        self.release_style(old);
+                    }
+                }
+            }
+
+            std::mem::swap(&mut fg_current, &mut fg_next);
+            std::mem::swap(&mut bg_current, &mut bg_next);
+            fg_next.iter_mut().for_each(|e| *e = [0.0; 3]);
+            bg_next.iter_mut().for_each(|e| *e = [0.0; 3]);
+        }
+    }
+
+    /// Set the double width or double height attribute of a line, adding missing lines.
+    ///
+    /// Columns of the line are still counted in its own cells, each of which takes up two
+    /// columns of the terminal when the line isn't [`LineSize::Normal`].
+    pub fn set_line_size(&mut self, line: usize, size: LineSize) {
+        while self.buffer.len() <= line {
+            self.buffer.push(Line::default());
+        }
+        if self.buffer[line].size != size {
+            self.buffer[line].size = size;
+            self.mark(line, 0..line_width(&self.buffer[line]));
+        }
+    }
+
+    /// Tile a pattern behind the buffer. Empty cells, the unstyled spaces [`TerminalBuffer::set`]
+    /// pads a line with, render the character of the pattern at their line and rendered column
+    /// instead, wrapping both horizontally and vertically.
+    ///
+    /// Only existing cells show the pattern, it doesn't extend lines. Written characters,
+    /// including spaces, and styled cells override it. Pattern rows should only contain single
+    /// column characters so the columns of the line don't change. An empty pattern removes the
+    /// background.
+    pub fn set_background_pattern(&mut self, pattern: &[&str]) {
+        self.background = pattern.iter().map(|row| row.chars().collect()).collect();
+        for line in 0..self.buffer.len() {
+            self.mark(line, 0..line_width(&self.buffer[line]));
+        }
+    }
 
+    /// Set the direction a line is displayed in, adding missing lines.
+    ///
+    /// Right to left lines are drawn in reverse and, in a buffer created with
+    /// [`TerminalBuffer::with_capacity`], aligned to the right of its columns.
+    pub fn set_direction(&mut self, line: usize, direction: Direction) {
+        while self.buffer.len() <= line {
+            self.buffer.push(Line::default());
+        }
+        if self.buffer[line].direction != direction {
+            self.buffer[line].direction = direction;
+            self.mark(line, 0..line_width(&self.buffer[line]));
+        }
+    }
+
+    /// The direction of a line, [`Direction::Ltr`] if the line doesn't exist.
+    pub fn direction(&self, line: usize) -> Direction {
+        self.buffer.get(line).map_or(Direction::Ltr, |l| l.direction)
+    }
+
+    /// The attribute of a line, [`LineSize::Normal`] if the line doesn't exist.
+    pub fn line_size(&self, line: usize) -> LineSize {
+        self.buffer.get(line).map_or(LineSize::Normal, |l| l.size)
+    }
+
+    /// Overwrite the cell at the line and rendered column with a character and style.
+    ///
+    /// Missing lines are added and a line shorter than the column is filled with unstyled spaces
+    /// first. Wide characters that are partially overwritten are replaced with spaces so the
+    /// other characters keep their columns. Setting a cell with `Style::default()` leaves the
+    /// character unstyled.
+    ///
+    /// With [`Overflow::Wrap`] a cell past the columns wraps onto the next line and lines past
+    /// the rows scroll the top line off of the buffer.
+    ///
+    /// # Panics
+    /// If the buffer has [`Overflow::Error`] and the cell doesn't fit. Use
+    /// [`TerminalBuffer::try_set`] to handle the error instead.
+    pub fn set(&mut self, line: usize, column: usize, ch: char, style: Style) {
+        if let Err(error) = self.set_cell(line, column, ch, style, Vec::new()) {
+            panic!("{}", error);
+        }
+    }
+
+    /// Same as [`TerminalBuffer::set`] but returns an error if the cell doesn't fit.
+    pub fn try_set(&mut self, line: usize, column: usize, ch: char, style: Style) -> Result<(), OverflowError> {
+        self.set_cell(line, column, ch, style, Vec::new())
+    }
+
+    /// Overwrite a cell, animating it with the frames when there are any.
+    fn set_cell(
+        &mut self,
+        line: usize,
+        column: usize,
+        ch: char,
+        style: Style,
+        frames: Vec<(char, Style)>,
+    ) -> Result<(), OverflowError> {
+        if self.overflow == Overflow::Error {
+            let (width, scale) = self.buffer.get(line).map_or((0, 1), |l| (line_width(l), l.size.scale()));
+            let width = width.max(column + char_width(ch).max(1));
+            self.check_size([width * scale], self.buffer.len().max(line + 1))?;
+        }
+
+        let space = || Character { empty: true, ..Character::new(' ', None) };
+        while self.buffer.len() <= line {
+            self.buffer.push(Line::default());
+        }
+        let width = line_width(&self.buffer[line]);
+        if column > width {
+            self.buffer[line].extend(iter::repeat_with(space).take(column - width));
+            self.mark(line, width..column);
+        }
+
+        let ch_width = char_width(ch).max(1);
+        let (start, end) = column_range(&self.buffer[line], column, column + ch_width);
+        let first_column = line_width(&self.buffer[line][..start]);
+        let last_column = first_column + line_width(&self.buffer[line][start..end]);
+
+        let key = (style != Style::default()).then(|| self.retain_style(style, 1));
+        let animation = match frames.is_empty() {
+            true => None,
+            false => {
+                let frames = frames
+                    .into_iter()
+                    .map(|(character, style)| (character, (style != Style::default()).then(|| self.retain_style(style, 1))))
+                    .collect();
+                Some(Box::new(AnimatedCell { frames, current: 0 }))
+            }
+        };
+        let mut cells = iter::repeat_with(space).take(column - first_column).collect::<Vec<_>>();
+        cells.push(Character { animation, ..Character::new(ch, key) });
+        cells.extend(iter::repeat_with(space).take(last_column.saturating_sub(column + ch_width)));
+
+        let removed = self.buffer[line].splice(start..end, cells).collect::<Vec<_>>();
+        self.release_characters(removed);
+        self.mark(line, first_column..last_column.max(column + ch_width));
+
+        if self.overflow == Overflow::Wrap {
+            self.wrap_to_capacity(line);
+        }
+        Ok(())
+    }
+
+    /// Animate the cell at the line and rendered column, cycling through the frames on each
+    /// [`TerminalBuffer::tick`]. The cell is [`TerminalBuffer::set`] to the first frame.
+    ///
+    /// Frames should all be as wide as the first frame so the other characters of the line keep
+    /// their columns. Overwriting the cell removes the animation, and restyling it only lasts
+    /// until the next tick.
+    ///
+    /// # Panics
+    /// If the buffer has [`Overflow::Error`] and the cell doesn't fit. Use
+    /// [`TerminalBuffer::try_animate`] to handle the error instead.
+    pub fn animate(&mut self, line: usize, column: usize, frames: Vec<(char, Style)>) {
+        if let Err(error) = self.try_animate(line, column, frames) {
+            panic!("{}", error);
+        }
+    }
+
+    /// Same as [`TerminalBuffer::animate`] but returns an error if the cell doesn't fit.
+    pub fn try_animate(&mut self, line: usize, column: usize, frames: Vec<(char, Style)>) -> Result<(), OverflowError> {
+        let Some((first, style)) = frames.first().cloned() else {
+            return Ok(());
+        };
+        self.set_cell(line, column, first, style, frames)
+    }
+
+    /// Advance every animated cell to its next frame, marking the cells dirty.
+    pub fn tick(&mut self) {
+        for line in 0..self.buffer.len() {
+            let mut column = 0;
+            for index in 0..self.buffer[line].len() {
+                let width = self.buffer[line][index].width();
+                if let Some(animation) = self.buffer[line][index].animation.as_mut() {
+                    animation.current = (animation.current + 1) % animation.frames.len();
+                    let (character, key) = animation.frames[animation.current];
+                    if let Some(mapped) = key.and_then(|key| self.styles.get_mut(&key)) {
+                        mapped.increment();
+                    }
+                    let cell = &mut self.buffer[line][index];
+                    cell.character = character;
+                    cell.width = char_width(character) as u8;
+                    cell.combining.clear();
+                    if let Some(old) = std::mem::replace(&mut cell.style, key) {
+                        self.release_style(old);
+                    }
+                    self.mark(line, column..column + width.max(char_width(character)));
+                }
+                column += width;
+            }
+        }
+    }
+
+    /// Line and rendered column of every cell changed since the last call, in order, clearing
+    /// them.
+    ///
+    /// Pushing, setting, replacing, restyling, dithering, and changing the size of a line mark
+    /// the cells they touch. Cells left empty by a shorter line, or lines that scrolled, are
+    /// included since the terminal still shows their old content. A cell can be marked even
+    /// when its content ends up the same.
+    pub fn take_dirty(&mut self) -> Vec<(usize, usize)> {
+        std::mem::take(&mut self.dirty).into_iter().collect()
+    }
+
+    /// Mark the rendered columns of a line as dirty.
+    fn mark(&mut self, line: usize, columns: Range<usize>) {
+        self.dirty.extend(columns.map(|column| (line, column)));
+    }
+
+    /// Widths of the lines in the range, clamped to the buffer.
+    fn widths(&self, lines: Range<usize>) -> Vec<usize> {
+        self.buffer[lines.start.min(self.buffer.len())..lines.end.min(self.buffer.len())]
+            .iter()
+            .map(|l| line_width(l))
+            .collect()
+    }
+
+    /// Mark every cell of the lines starting at `first` that had the widths before they changed,
+    /// covering both their old and new content.
+    fn mark_lines(&mut self, first: usize, before: Vec<usize>) {
+        for (i, before) in before.into_iter().enumerate() {
+            let after = self.buffer.get(first + i).map_or(0, |l| line_width(l));
+            self.mark(first + i, 0..before.max(after));
+        }
+    }
+
+    /// Resolve the style a character references.
+    fn style_of(&self, character: &Character) -> Option<&Style> {
+        character.style.and_then(|key| self.styles.get(&key)).map(|mapped| &mapped.style)
+    }
+
+    /// The character covering the line and rendered column. Every column of a wide character
+    /// maps to it.
+    fn character_at(&self, line: usize, column: usize) -> Option<&Character> {
+        let line = self.buffer.get(line)?;
+        let (index, _) = column_range(line, column, column + 1);
+        line.get(index)
+    }
+
+    /// The style of the cell at the line and rendered column, Ex: for hit testing mouse clicks.
+    /// `None` if the cell is unstyled or doesn't exist.
+    pub fn style_at(&self, line: usize, column: usize) -> Option<&Style> {
+        self.style_of(self.character_at(line, column)?)
+    }
+
+    /// The character of the cell at the line and rendered column, `None` if the cell doesn't exist.
+    pub fn char_at(&self, line: usize, column: usize) -> Option<char> {
+        self.character_at(line, column).map(Character::character)
+    }
+
+    /// Line, rendered column, and character of the cells in this buffer that differ from
+    /// `other`, either by character or by resolved style.
+    ///
+    /// Cells that don't exist in `other` are always changed. Cells that only exist in `other`
+    /// have no character in this buffer and are not yielded.
+    pub fn changed_cells<'a>(&'a self, other: &'a TerminalBuffer) -> impl Iterator<Item = (usize, usize, &'a Character)> {
+        self.buffer.iter().enumerate().flat_map(move |(l, line)| {
+            let mut column = 0;
+            line.iter().enumerate().filter_map(move |(c, character)| {
+                let start = column;
+                column += character.width();
+                let changed = match other.buffer.get(l).and_then(|line| line.get(c)) {
+                    Some(previous) => {
+                        previous.character != character.character
+                            || previous.combining != character.combining
+                            || other.style_of(previous) != self.style_of(character)
+                    }
+                    None => true,
+                };
+                changed.then_some((l, start, character))
+            })
+        })
+    }
+
+    /// Release the style references held by the removed characters.
+    fn release_characters<I: IntoIterator<Item = Character>>(&mut self, characters: I) {
+        for character in characters {
+            if let Some(key) = character.style {
+                self.release_style(key);
+            }
+            for (_, key) in character.animation.map_or(Vec::new(), |animation| animation.frames) {
+                if let Some(key) = key {
+                    self.release_style(key);
+                }
+            }
+        }
+    }
+
+    /// Remove all the text leaving a single empty line. Every style is released so the style map
+    /// is empty afterwards. The settings of the buffer, like the tab width, are kept.
+    pub fn clear(&mut self) {
+        let before = self.widths(0..self.buffer.len());
+        let removed = std::mem::replace(&mut self.buffer, vec![Line::default()]);
+        self.release_characters(removed.into_iter().flat_map(|line| line.characters));
+        self.mark_lines(0, before);
+        self.clamp_cursor();
+    }
+
+    /// Number of distinct styles referenced by the characters of the buffer.
+    pub fn style_count(&self) -> usize {
+        self.styles.len()
+    }
+
+    /// Replace the text starting at the first line and start column through the last line and
+    /// end column with the given chunk.
+    ///
+    /// The text before the start column on the first line and the text after the end column on
+    /// the last line are merged with the first and last lines of the chunk.
+    ///
+    /// Columns are rendered columns so wide characters span multiple columns. A wide character
+    /// that is partially in the range is replaced.
+    ///
+    /// When the start column equals the end column nothing is deleted and the chunk is inserted at
+    /// that column. A start column equal to the width of the line appends to the line.
+    ///
+    /// With [`Overflow::Wrap`] lines past the columns wrap onto the next line and lines past the
+    /// rows scroll the top line off of the buffer. The buffer is left unchanged when the ranges
+    /// don't fit it or, with [`Overflow::Error`], the replaced text doesn't.
+    pub fn replace<D: Display, R1: ReplaceRange, R2: ReplaceRange>(
+        &mut self,
+        lines: R1,
+        columns: R2,
+        chunk: D,
+    ) -> Result<(), ReplaceError> {
+        if lines.start() >= self.buffer.len() || lines.start() > lines.end() {
+            return Err(ReplaceError::Lines { start: lines.start(), end: lines.end(), lines: self.buffer.len() });
+        }
+        let first = lines.start();
+        let last = lines.end_bounded(self.buffer.len()).max(first + 1) - 1;
+
+        let end = columns.end_bounded(line_width(&self.buffer[last]));
+        if columns.start() > line_width(&self.buffer[first])
+            || columns.start() > columns.end()
+            || (first == last && end < columns.start())
+        {
+            return Err(ReplaceError::Columns {
+                start: columns.start(),
+                end: columns.end(),
+                width: line_width(&self.buffer[first]),
+            });
+        }
+        let (start, end) = if first == last {
+            column_range(&self.buffer[first], columns.start(), end)
+        } else {
+            (
+                column_range(&self.buffer[first], columns.start(), usize::MAX).0,
+                column_range(&self.buffer[last], 0, end).1,
+            )
+        };
+
+        let first_column = line_width(&self.buffer[first][..start]);
+        let before = self.widths(first..self.buffer.len());
+
+        // Convert the replacement to a pseudo buffer to merge into the gap
+        let mut pseudo = chunk
+            .to_string()
+            .split('\n')
+            .enumerate()
+            .map(|(i, line)| {
+                let column = if i == 0 { first_column } else { 0 };
+                to_characters(line, None, column, self.tab_width)
+            })
+            .collect::<Vec<_>>();
+        if self.overflow == Overflow::Error {
+            // The first line keeps its size and text before the range, the last gets the tail
+            let mut widths = pseudo.iter().map(|characters| line_width(characters)).collect::<Vec<_>>();
+            *widths.last_mut().unwrap() += line_width(&self.buffer[last][end..]);
+            widths[0] = (widths[0] + first_column) * self.buffer[first].size.scale();
+            let lines = self.buffer.len() - (last - first) + widths.len() - 1;
+            self.check_size(widths, lines).map_err(ReplaceError::Overflow)?;
+        }
+
+        // Cut out the replaced text, keeping the tail of the last line to merge back in
+        let tail = self.buffer[last].split_off(end);
+        let removed = self.buffer.drain(first + 1..=last).flat_map(|l| l.characters).collect::<Vec<_>>();
+        self.release_characters(removed);
+        let removed = self.buffer[first].split_off(start);
+        self.release_characters(removed);
+        pseudo.last_mut().unwrap().extend(tail);
+        self.attach_combining(first, &mut pseudo[0]);
+
+        let mut pseudo = pseudo.into_iter();
+        self.buffer[first].extend(pseudo.next().unwrap());
+        let lines = pseudo.len();
+        self.buffer.splice(first + 1..first + 1, pseudo.map(Line::from));
+
+        // Lines after the replacement only move when the number of lines changed
+        let changed = if last - first == lines { lines + 1 } else { before.len().max(self.buffer.len() - first) };
+        let before = before.into_iter().chain(iter::repeat(0)).take(changed).collect::<Vec<_>>();
+        let after = line_width(&self.buffer[first]);
+        self.mark(first, first_column..before[0].max(after));
+        self.mark_lines(first + 1, before[1..].to_vec());
+        if self.overflow == Overflow::Wrap {
+            self.wrap_to_capacity(first);
+        }
+        self.clamp_cursor();
+        Ok(())
+    }
+}
+
+impl TerminalBuffer {
+    /// Render the buffer with the minimal style transitions between characters.
+    ///
+    /// With `leading_reset` a `\x1b[0m` is emitted before the first run of characters when it is
+    /// styled, on whichever line it is, so any style already active in the terminal doesn't bleed
+    /// into the content.
+    fn render<W: Write>(&self, out: &mut W, mut leading_reset: bool) -> std::fmt::Result {
         let mut curr_style = Style::default();
-        for line in self.buffer.iter() {
-            let mut line_buffer = String::new();
-            for character in line.iter() {
-                let style = match character.style {
-                    Some(key) => self.styles.get(&key).unwrap().style.clone(),
-                    None => Style::default()
+        for (l, line) in self.buffer.iter().enumerate() {
+            if l > 0 {
+                out.write_char('\n')?;
+            }
+            self.render_characters(out, l, line, 0..usize::MAX, &mut curr_style, &mut leading_reset)?;
+        }
+        if !self.buffer.is_empty() {
+            out.write_str(curr_style.reset_sequence().as_str())?;
+        }
+        Ok(())
+    }
+
+    /// Render the characters of a line transitioning from and updating the current style.
+    ///
+    /// `leading_reset` is cleared by the first character written, which is preceded by a
+    /// `\x1b[0m` when it is styled.
+    ///
+    /// Only the characters in the rendered columns are written. The columns of a wide character
+    /// that is partially in the range are written as unstyled spaces. Right to left lines are
+    /// written in reverse, padded to the capacity of the buffer when the whole line is written.
+    fn render_characters<W: Write>(
+        &self,
+        out: &mut W,
+        l: usize,
+        line: &Line,
+        columns: Range<usize>,
+        curr_style: &mut Style,
+        leading_reset: &mut bool,
+    ) -> std::fmt::Result {
+        out.write_str(line.size.sequence())?;
+        let pattern = match self.background.len() {
+            0 => None,
+            rows => Some(&self.background[l % rows]).filter(|row| !row.is_empty()),
+        };
+        let width = line_width(line);
+        let cells: Box<dyn Iterator<Item = (usize, &Character)>> = match line.direction {
+            Direction::Ltr => Box::new(line.iter().scan(0, |column, c| {
+                let start = *column;
+                *column += c.width();
+                Some((start, c))
+            })),
+            Direction::Rtl => {
+                let pad = match self.capacity {
+                    Some((_, max)) if columns == (0..usize::MAX) => (max / line.size.scale()).saturating_sub(width),
+                    _ => 0,
                 };
-                if curr_style != style {
-                    line_buffer.push_str(curr_style.reset_sequence().as_str());
-                    line_buffer.push_str(style.sequence().as_str());
-                    curr_style = style;
+                if pad > 0 {
+                    *leading_reset = false;
+                    out.write_str(curr_style.reset_sequence().as_str())?;
+                    *curr_style = Style::default();
+                    out.write_str(&" ".repeat(pad))?;
+                }
+                Box::new(line.iter().rev().scan(width, |column, c| {
+                    *column -= c.width();
+                    Some((*column, c))
+                }))
+            }
+        };
+        for (start, character) in cells {
+            let end = start + character.width();
+            let inside = start >= columns.start && start < columns.end && end <= columns.end;
+            let overlap = end.min(columns.end).saturating_sub(start.max(columns.start));
+            if !inside && overlap == 0 {
+                continue;
+            }
+
+            let style = match character.style {
+                Some(key) if inside => self.styles.get(&key).unwrap().style.clone(),
+                _ => Style::default()
+            };
+            if *curr_style != style {
+                if *leading_reset {
+                    out.write_str("\x1b[0m")?;
+                }
+                out.write_str(curr_style.reset_sequence().as_str())?;
+                out.write_str(style.sequence().as_str())?;
+                *curr_style = style;
+            }
+            *leading_reset = false;
+            if !inside {
+                out.write_str(&" ".repeat(overlap))?;
+                continue;
+            }
+            match pattern {
+                Some(row) if character.empty && character.style.is_none() => {
+                    out.write_char(row[start % row.len()])?
+                }
+                _ => {
+                    out.write_char(character.character)?;
+                    out.write_str(&character.combining)?;
                 }
-                line_buffer.push(character.character);
             }
-            buffer.push(line_buffer);
         }
-        if buffer.len() > 0 {
-            buffer.last_mut().unwrap().push_str(curr_style.reset_sequence().as_str());
+        Ok(())
+    }
+
+    /// Render a single line on its own, starting with a `\x1b[0m` reset when the first character
+    /// is styled and ending with the reset of the last style. `None` if the line doesn't exist.
+    ///
+    /// The line doesn't depend on the styles of the previous lines, so it can be written after
+    /// moving the cursor, `\x1b[{line};1H`, to update only that line.
+    pub fn render_line(&self, line: usize) -> Option<String> {
+        self.buffer.get(line)?;
+        Some(self.render_window(line, 0..usize::MAX))
+    }
+
+    /// Render the rectangle of `lines` x `columns`, Ex: the visible part of a scrolled view.
+    ///
+    /// Each line of the region is self-contained like [`TerminalBuffer::render_line`], so the
+    /// region can be written anywhere on the screen. Columns are rendered columns and the
+    /// columns of a wide character cut by the edge of the region are unstyled spaces. The region
+    /// is clamped to the buffer.
+    pub fn render_region<R1: RangeBounds<usize>, R2: RangeBounds<usize>>(&self, lines: R1, columns: R2) -> String {
+        let (line_start, line_end) = clamp_range(&lines, self.buffer.len());
+        let (col_start, col_end) = clamp_range(&columns, usize::MAX);
+        (line_start..line_end)
+            .map(|line| self.render_window(line, col_start..col_end))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// Render the columns of a line on its own, starting with a reset when the first character
+    /// is styled and ending with the reset of the last style.
+    fn render_window(&self, line: usize, columns: Range<usize>) -> String {
+        let mut output = String::new();
+        let mut curr_style = Style::default();
+        let _ = self.render_characters(&mut output, line, &self.buffer[line], columns, &mut curr_style, &mut true);
+        output.push_str(curr_style.reset_sequence().as_str());
+        output
+    }
+
+    /// Render the buffer with an unstyled column ruler above it and a line number gutter to the
+    /// left of it, for debugging layout.
+    ///
+    /// The ruler has the units digit of every rendered column and the tens digit above every
+    /// tenth column. The ruler and gutter aren't part of the buffer.
+    pub fn with_ruler(&self) -> String {
+        let gutter = self.buffer.len().saturating_sub(1).to_string().len();
+        let width = self.buffer.iter().map(|line| line_width(line)).max().unwrap_or(0);
+
+        let mut output = String::new();
+        if width > 10 {
+            let tens = (0..width)
+                .map(|column| match column % 10 {
+                    0 if column > 0 => char::from(b'0' + ((column / 10) % 10) as u8),
+                    _ => ' ',
+                })
+                .collect::<String>();
+            let _ = writeln!(output, "{}{}", " ".repeat(gutter + 1), tens.trim_end());
+        }
+        output.push_str(&" ".repeat(gutter + 1));
+        for column in 0..width {
+            output.push_str(&(column % 10).to_string());
+        }
+
+        for line in 0..self.buffer.len() {
+            let _ = write!(output, "\n{:>gutter$}|{}", line, self.render_line(line).unwrap_or_default());
+        }
+        output
+    }
+
+    /// Exact byte length of the rendered buffer, same as `self.to_string().len()`, without
+    /// building the rendered string.
+    pub fn rendered_len(&self) -> usize {
+        let mut counter = ByteCounter(0);
+        let _ = self.render(&mut counter, false);
+        counter.0
+    }
+
+    /// Render the buffer with a `\x1b[0m` reset before the first run of characters when it is
+    /// styled, even when it isn't on the first line.
+    ///
+    /// Useful for pagers and log viewers where the ambient terminal style is unknown.
+    pub fn render_with_leading_reset(&self) -> String {
+        let mut output = String::new();
+        let _ = self.render(&mut output, true);
+        output
+    }
+
+    /// Render the buffer followed by the sequences that set the shape, position, and visibility of
+    /// the terminal cursor to the buffer's [`Cursor`].
+    ///
+    /// The position assumes the buffer is written from the top left of the screen. The column is
+    /// doubled on double width and double height lines.
+    pub fn render_with_cursor(&self) -> String {
+        let mut output = String::new();
+        let _ = self.render(&mut output, false);
+        let scale = self.buffer.get(self.cursor.line).map_or(1, |l| l.size.scale());
+        let _ = write!(
+            output,
+            "{}\x1b[{};{}H\x1b[?25{}",
+            self.cursor.shape.sequence(),
+            self.cursor.line + 1,
+            self.cursor.column * scale + 1,
+            if self.cursor.visible { 'h' } else { 'l' },
+        );
+        output
+    }
+
+    /// The text of the buffer without styles or wrap markers. Lines that were wrapped are joined
+    /// back into the line they were pushed as, including the space dropped at the break.
+    pub fn to_plain(&self) -> String {
+        let mut output = String::new();
+        for (l, line) in self.buffer.iter().enumerate() {
+            if l > 0 && !line.wrapped {
+                output.push('\n');
+            }
+            if line.dropped_space {
+                output.push(' ');
+            }
+            for character in line.iter().filter(|c| !c.marker) {
+                output.push(character.character);
+                output.push_str(&character.combining);
+            }
+        }
+        output
+    }
+
+    /// Render the buffer into any `fmt::Write` target, Ex: appending to an existing `String`,
+    /// without building an intermediate string. Same output as the `Display` impl.
+    pub fn render_fmt<W: Write>(&self, w: &mut W) -> std::fmt::Result {
+        self.render(w, false)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl TerminalBuffer {
+    /// Serialize the buffer as an array of lines where each line is an array of
+    /// `{"text": ..., "style": ...}` runs. Unstyled runs have a `null` style.
+    ///
+    /// Only the text and styles of the cells are kept. Tabs are stored as the spaces they were
+    /// expanded to and wrap markers as plain text, and the size, direction, and wrapped state of
+    /// the lines, animations, and the settings of the buffer, like the cursor, aren't stored.
+    pub fn to_json(&self) -> String {
+        let lines = self
+            .buffer
+            .iter()
+            .map(|line| {
+                let mut runs: Vec<(&Character, String)> = Vec::new();
+                for character in line.iter() {
+                    match runs.last_mut() {
+                        Some((first, text)) if first.style == character.style => {
+                            text.push(character.character);
+                            text.push_str(&character.combining);
+                        }
+                        _ => runs.push((character, format!("{}{}", character.character, character.combining))),
+                    }
+                }
+                runs.into_iter()
+                    .map(|(first, text)| Run { text, style: self.style_of(first).cloned() })
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        serde_json::to_string(&lines).unwrap()
+    }
+
+    /// Reconstruct a buffer from the output of [`TerminalBuffer::to_json`].
+    ///
+    /// The buffer renders the same text and styles as the one that was serialized, but what
+    /// [`TerminalBuffer::to_json`] doesn't store is left at its defaults.
+    pub fn from_json(json: &str) -> Result<TerminalBuffer, serde_json::Error> {
+        let lines: Vec<Vec<Run>> = serde_json::from_str(json)?;
+        let mut buffer = TerminalBuffer::new();
+        for (i, line) in lines.into_iter().enumerate() {
+            if i > 0 {
+                buffer.push('\n');
+            }
+            for run in line {
+                match run.style {
+                    Some(style) => buffer.push_styled(style, run.text),
+                    None => buffer.push(run.text),
+                }
+            }
         }
-        write!(f, "{}", buffer.join("\n"))
+        Ok(buffer)
+    }
+}
+
+impl Display for TerminalBuffer {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        self.render(f, false)
     }
 }
 
-pub fn test() {
-    let mut buffer = TerminalBuffer::new();
-    buffer.push_styled(Style::builder().fg(color!(red)).bold(), "First Buffer\n");
-    buffer.push("    ");
-    buffer.push_styled(Style::builder().bold(), "of styled text");
-    {
-        let line = buffer.buffer.get(0).unwrap();
-        buffer.replace(0, 0..5, "Second")
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Number of references the buffer holds to the style.
+    fn refs(buffer: &TerminalBuffer, style: &Style) -> usize {
+        buffer.styles.get(&style.hash_key()).map_or(0, |mapped| mapped.refs)
+    }
+
+    #[test]
+    fn restyle_moves_references() {
+        let red = Style::builder().fg(Color::RED);
+        let blue = Style::builder().fg(Color::BLUE);
+        let mut buffer = TerminalBuffer::new();
+        buffer.push_styled(red.clone(), "abcd");
+
+        buffer.restyle(0..1, 1..3, blue.clone());
+        assert_eq!(buffer.to_string(), "\x1b[31ma\x1b[39m\x1b[34mbc\x1b[39m\x1b[31md\x1b[39m");
+        assert_eq!(refs(&buffer, &red), 2);
+        assert_eq!(refs(&buffer, &blue), 2);
+
+        buffer.restyle(.., .., Style::default());
+        assert_eq!(buffer.to_string(), "abcd");
+        assert_eq!(buffer.style_count(), 0);
+    }
+
+    #[test]
+    fn replace_empty_range_inserts() {
+        let mut buffer = TerminalBuffer::new();
+        buffer.push("abcd");
+        buffer.replace(0, 2..2, "XY").unwrap();
+        assert_eq!(buffer.to_plain(), "abXYcd");
+
+        buffer.replace(0, 6..6, "!").unwrap();
+        assert_eq!(buffer.to_plain(), "abXYcd!");
+    }
+
+    #[test]
+    fn cursor_clamps_to_buffer() {
+        let mut buffer = TerminalBuffer::new();
+        buffer.push("abc\nde");
+
+        buffer.move_by(5, 0);
+        assert_eq!((buffer.cursor().line, buffer.cursor().column), (1, 0));
+        buffer.move_by(0, 10);
+        assert_eq!((buffer.cursor().line, buffer.cursor().column), (1, 2));
+        buffer.move_by(-5, -10);
+        assert_eq!((buffer.cursor().line, buffer.cursor().column), (0, 0));
+
+        buffer.move_to(1, 2);
+        buffer.replace(0..2, .., "x").unwrap();
+        assert_eq!((buffer.cursor().line, buffer.cursor().column), (0, 1));
+        buffer.clear();
+        assert_eq!((buffer.cursor().line, buffer.cursor().column), (0, 0));
+    }
+
+    #[test]
+    fn cursor_moves_across_tab() {
+        let mut buffer = TerminalBuffer::new();
+        buffer.set_tab_width(4);
+        buffer.push("a\tb");
+        assert_eq!(buffer.logical_columns(0), vec![0, 1, 4]);
+
+        buffer.move_by(0, 1);
+        assert_eq!(buffer.cursor().column, 1);
+        buffer.move_by(0, 1);
+        assert_eq!(buffer.cursor().column, 4);
+        buffer.move_by(0, -1);
+        assert_eq!(buffer.cursor().column, 1);
+    }
+
+    #[test]
+    fn leading_reset_only_before_styled_first_run() {
+        let bold = Style::builder().bold();
+        let mut buffer = TerminalBuffer::new();
+        buffer.push_styled(bold.clone(), "x");
+        assert_eq!(buffer.render_with_leading_reset(), "\x1b[0m\x1b[1mx\x1b[22m");
+
+        let mut buffer = TerminalBuffer::new();
+        buffer.push("\n");
+        buffer.push_styled(bold.clone(), "x");
+        assert_eq!(buffer.render_with_leading_reset(), "\n\x1b[0m\x1b[1mx\x1b[22m");
+
+        let mut buffer = TerminalBuffer::new();
+        buffer.push("a");
+        buffer.push_styled(bold, "x");
+        assert_eq!(buffer.render_with_leading_reset(), "a\x1b[1mx\x1b[22m");
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn json_round_trip() {
+        let mut buffer = TerminalBuffer::new();
+        buffer.push_styled(Style::builder().fg(Color::RED).bold(), "red");
+        buffer.push(" plain\n");
+        buffer.push_styled(Style::builder().bg(Color::BLUE), "blue");
+        buffer.push_styled(Style::builder().italic(), "italic");
+
+        let json = buffer.to_json();
+        let restored = TerminalBuffer::from_json(&json).unwrap();
+        assert_eq!(restored.to_string(), buffer.to_string());
+        assert_eq!(restored.to_json(), json);
+        assert_eq!(restored.style_count(), 3);
+    }
+
+    #[test]
+    fn equal_colors_share_a_style_and_render_the_same() {
+        let mut buffer = TerminalBuffer::new();
+        buffer.push_styled(Style::builder().fg(Color::Ansi(1)), "a");
+        buffer.push_styled(Style::builder().fg(Color::RED), "b");
+        assert_eq!(buffer.style_count(), 1);
+        assert_eq!(buffer.to_string(), "\x1b[31mab\x1b[39m");
+
+        let mut buffer = TerminalBuffer::new();
+        buffer.push_styled(Style::builder().fg(Color::BrightRed), "a");
+        buffer.push_styled(Style::builder().fg(Color::Ansi(9)), "b");
+        assert_eq!(buffer.style_count(), 1);
+        assert_eq!(buffer.to_string(), "\x1b[91mab\x1b[39m");
+    }
+
+    #[test]
+    fn wrap_drops_the_space_at_the_break() {
+        let mut buffer = TerminalBuffer::new();
+        buffer.set_wrap_width(Some(5));
+        buffer.push("Hello world");
+        assert_eq!(buffer.to_string(), "Hello\nworld");
+        assert_eq!(buffer.to_plain(), "Hello world");
+
+        let mut buffer = TerminalBuffer::new();
+        buffer.set_wrap_width(Some(9));
+        buffer.push("The quick brown");
+        assert_eq!(buffer.to_string(), "The quick\nbrown");
+
+        let bold = Style::builder().bold();
+        let mut buffer = TerminalBuffer::new();
+        buffer.set_wrap_width(Some(10));
+        buffer.push_styled(bold.clone(), "jumps over the lazy dog");
+        assert_eq!(buffer.to_plain(), "jumps over the lazy dog");
+        assert_eq!(refs(&buffer, &bold), 22);
+        assert_eq!(buffer.to_string(), "\x1b[1mjumps over\nthe lazy \ndog\x1b[22m");
+    }
+
+    #[test]
+    fn background_pattern_shows_in_empty_cells() {
+        let mut buffer = TerminalBuffer::new();
+        buffer.push("a b");
+        buffer.set(1, 3, 'x', Style::default());
+        buffer.set(1, 1, ' ', Style::default());
+        buffer.set_background_pattern(&["-."]);
+        assert_eq!(buffer.to_string(), "a b\n- -x");
+    }
+
+    #[test]
+    fn set_replace_and_animate_error_on_overflow() {
+        let mut buffer = TerminalBuffer::with_capacity(2, 4);
+        buffer.set_overflow(Overflow::Error);
+        buffer.push("abcd");
+
+        assert_eq!(buffer.try_set(0, 4, 'x', Style::default()), Err(OverflowError::Columns { width: 5, columns: 4 }));
+        assert_eq!(buffer.try_set(2, 0, 'x', Style::default()), Err(OverflowError::Rows { lines: 3, rows: 2 }));
+        assert_eq!(
+            buffer.try_animate(0, 4, vec![('x', Style::default()), ('y', Style::default())]),
+            Err(OverflowError::Columns { width: 5, columns: 4 })
+        );
+        assert_eq!(
+            buffer.replace(0, 1..2, "XY"),
+            Err(ReplaceError::Overflow(OverflowError::Columns { width: 5, columns: 4 }))
+        );
+        assert_eq!(
+            buffer.replace(0, 4..4, "\n\n"),
+            Err(ReplaceError::Overflow(OverflowError::Rows { lines: 3, rows: 2 }))
+        );
+        assert_eq!(buffer.to_plain(), "abcd");
+
+        buffer.set(1, 3, 'x', Style::default());
+        buffer.replace(0, 1..3, "XY").unwrap();
+        assert_eq!(buffer.to_plain(), "aXYd\n   x");
+    }
+
+    #[test]
+    fn set_replace_and_animate_wrap_on_overflow() {
+        let mut buffer = TerminalBuffer::with_capacity(2, 4);
+        buffer.set_overflow(Overflow::Wrap);
+        buffer.push("abcd");
+
+        buffer.set(0, 5, 'x', Style::default());
+        assert_eq!(buffer.to_string(), "abcd\n x");
+        buffer.replace(1, 0..1, "123").unwrap();
+        assert_eq!(buffer.to_string(), "abcd\n123x");
+
+        let bold = Style::builder().bold();
+        buffer.animate(1, 4, vec![('y', bold.clone()), ('z', Style::default())]);
+        assert_eq!(buffer.to_string(), "123x\n\x1b[1my\x1b[22m");
+        assert_eq!(refs(&buffer, &bold), 2);
+        buffer.tick();
+        assert_eq!(buffer.to_string(), "123x\nz");
+    }
+
+    #[test]
+    fn replace_within_a_line() {
+        let bold = Style::builder().bold();
+        let mut buffer = TerminalBuffer::new();
+        buffer.push_styled(bold.clone(), "First Buffer");
+        buffer.replace(0, 0..5, "Second").unwrap();
+        assert_eq!(buffer.to_plain(), "Second Buffer");
+        assert_eq!(buffer.to_string(), "Second\x1b[1m Buffer\x1b[22m");
+        assert_eq!(refs(&buffer, &bold), 7);
+    }
+
+    #[test]
+    fn replace_across_lines() {
+        let mut buffer = TerminalBuffer::new();
+        buffer.push("first\nsecond\nthird");
+        buffer.replace(0..2, 2..4, "X\nY").unwrap();
+        assert_eq!(buffer.to_plain(), "fiX\nYnd\nthird");
+
+        buffer.replace(0..3, 2..3, "-").unwrap();
+        assert_eq!(buffer.to_plain(), "fi-rd");
+        assert_eq!(buffer.replace(1, .., "x"), Err(ReplaceError::Lines { start: 1, end: 2, lines: 1 }));
+    }
+
+    #[test]
+    fn float_colors_share_a_style() {
+        let mut buffer = TerminalBuffer::new();
+        buffer.push_styled(Style::builder().fg(Color::HSL { h: 200, s: 0.5, l: 0.5 }), "a");
+        buffer.push_styled(Style::builder().fg(Color::HSL { h: 200, s: 0.5 + 1e-7, l: 0.5 }), "b");
+        assert_eq!(buffer.style_count(), 1);
+        assert_eq!(refs(&buffer, &Style::builder().fg(Color::HSL { h: 200, s: 0.5, l: 0.5 })), 2);
+    }
+
+    #[test]
+    fn take_dirty_returns_the_changed_cells() {
+        let mut buffer = TerminalBuffer::new();
+        buffer.push("abc\ndef");
+        buffer.take_dirty();
+
+        buffer.set(0, 1, 'x', Style::default());
+        buffer.set(1, 2, 'y', Style::builder().bold());
+        assert_eq!(buffer.take_dirty(), vec![(0, 1), (1, 2)]);
+        assert_eq!(buffer.take_dirty(), vec![]);
+    }
+
+    #[test]
+    fn tick_advances_animated_cells() {
+        let mut buffer = TerminalBuffer::new();
+        buffer.push("ab");
+        buffer.animate(0, 1, vec![('-', Style::default()), ('|', Style::default())]);
+        buffer.take_dirty();
+
+        buffer.tick();
+        assert_eq!(buffer.to_plain(), "a|");
+        assert_eq!(buffer.take_dirty(), vec![(0, 1)]);
+        buffer.tick();
+        assert_eq!(buffer.to_plain(), "a-");
+    }
+
+    #[test]
+    fn overwriting_an_animated_cell_releases_its_frames() {
+        let bold = Style::builder().bold();
+        let italic = Style::builder().italic();
+        let mut buffer = TerminalBuffer::new();
+        buffer.animate(0, 0, vec![('-', bold.clone()), ('|', italic.clone())]);
+        assert_eq!(refs(&buffer, &bold), 2);
+        assert_eq!(refs(&buffer, &italic), 1);
+        buffer.tick();
+        assert_eq!(refs(&buffer, &bold), 1);
+        assert_eq!(refs(&buffer, &italic), 2);
+
+        buffer.set(0, 0, 'x', Style::default());
+        assert!(buffer.character_at(0, 0).unwrap().animation().is_none());
+        assert_eq!(buffer.style_count(), 0);
+    }
+
+    #[test]
+    fn clear_releases_every_style() {
+        let mut buffer = TerminalBuffer::new();
+        buffer.push_styled(Style::builder().bold(), "a\nb");
+        buffer.push_styled(Style::builder().fg(Color::RED), "c");
+        buffer.animate(1, 3, vec![('-', Style::builder().italic()), ('|', Style::builder().dim())]);
+        assert_eq!(buffer.style_count(), 4);
+
+        buffer.clear();
+        assert_eq!(buffer.style_count(), 0);
+        assert_eq!(buffer.to_plain(), "");
+    }
+
+    #[test]
+    fn gradient_fill_blends_the_corners() {
+        let mut buffer = TerminalBuffer::new();
+        buffer.push("   \n   \n   ");
+        let (top_left, top_right) = (Color::RGB { r: 0, g: 0, b: 0 }, Color::RGB { r: 200, g: 0, b: 0 });
+        let (bottom_left, bottom_right) = (Color::RGB { r: 0, g: 200, b: 0 }, Color::RGB { r: 0, g: 0, b: 200 });
+        buffer.gradient_fill(.., .., top_left, top_right, bottom_left, bottom_right);
+
+        let bg = |buffer: &TerminalBuffer, line, column| {
+            buffer.style_of(buffer.character_at(line, column).unwrap()).and_then(|style| style.bg)
+        };
+        assert_eq!(bg(&buffer, 0, 0), Some(top_left));
+        assert_eq!(bg(&buffer, 0, 2), Some(top_right));
+        assert_eq!(bg(&buffer, 2, 0), Some(bottom_left));
+        assert_eq!(bg(&buffer, 2, 2), Some(bottom_right));
+        assert_eq!(bg(&buffer, 1, 1), Some(Color::RGB { r: 50, g: 50, b: 50 }));
+    }
+
+    #[test]
+    fn replace_range_bounds() {
+        let bounds = |range: &dyn ReplaceRange| (range.start(), range.end());
+        assert_eq!(bounds(&3usize), (3, 4));
+        assert_eq!(bounds(&..), (0, usize::MAX));
+        assert_eq!(bounds(&(2..5)), (2, 5));
+        assert_eq!(bounds(&..5), (0, 5));
+        assert_eq!(bounds(&(2..)), (2, usize::MAX));
+        assert_eq!(bounds(&usize::MAX), (usize::MAX, usize::MAX));
+        assert_eq!(bounds(&..=5), (0, 6));
+        assert_eq!(bounds(&..=usize::MAX), (0, usize::MAX));
+        assert_eq!(bounds(&(2..=5)), (2, 6));
+        assert_eq!(bounds(&(2..=usize::MAX)), (2, usize::MAX));
+
+        let mut exhausted = 2..=2;
+        exhausted.next();
+        assert_eq!(bounds(&exhausted), (2, 2));
+        assert_eq!((2..=5).end_bounded(4), 4);
+    }
+
+    #[test]
+    fn scroll_region_keeps_the_lines_outside_fixed() {
+        let mut buffer = TerminalBuffer::new();
+        buffer.push("top\na\nb\nbottom");
+        assert_eq!(buffer.set_scroll_region(2, 1), Err(ScrollRegionError { top: 2, bottom: 1 }));
+        buffer.set_scroll_region(1, 2).unwrap();
+
+        buffer.push("\nc\nd\ne");
+        assert_eq!(buffer.to_plain(), "top\nd\ne\nbottom");
+    }
+
+    #[test]
+    fn changed_cells_yields_only_the_differing_cells() {
+        let mut previous = TerminalBuffer::new();
+        previous.push("abc\ndef");
+        let mut buffer = TerminalBuffer::new();
+        buffer.push("abc\ndef");
+        assert_eq!(buffer.changed_cells(&previous).count(), 0);
+
+        buffer.set(0, 1, 'x', Style::default());
+        buffer.set(1, 2, 'f', Style::builder().bold());
+        let changed = |buffer: &TerminalBuffer, other: &TerminalBuffer| {
+            buffer.changed_cells(other).map(|(l, c, ch)| (l, c, ch.character())).collect::<Vec<_>>()
+        };
+        assert_eq!(changed(&buffer, &previous), [(0, 1, 'x'), (1, 2, 'f')]);
+
+        // Cells past the end of the other buffer are changed, cells only in it aren't yielded
+        buffer.push("g\nh");
+        assert_eq!(changed(&buffer, &previous), [(0, 1, 'x'), (1, 2, 'f'), (1, 3, 'g'), (2, 0, 'h')]);
+        assert_eq!(changed(&previous, &buffer), [(0, 1, 'b'), (1, 2, 'f')]);
+    }
+
+    #[test]
+    fn replace_straddling_a_wide_character() {
+        let replaced = |columns: Range<usize>, chunk: &str| {
+            let mut buffer = TerminalBuffer::new();
+            buffer.push("a日b");
+            buffer.replace(0, columns, chunk).unwrap();
+            buffer.to_plain()
+        };
+        // 日 takes up columns 1 and 2, covering either column replaces all of it
+        assert_eq!(replaced(2..3, "x"), "axb");
+        assert_eq!(replaced(1..2, "xy"), "axyb");
+        assert_eq!(replaced(0..2, "x"), "xb");
+        assert_eq!(replaced(3..4, "x"), "a日x");
+    }
+
+    #[test]
+    fn rendered_len_matches_the_rendered_string() {
+        let mut buffer = TerminalBuffer::new();
+        buffer.push_styled(Style::builder().fg(Color::RED).bold(), "ab");
+        buffer.push("c\t日\n");
+        buffer.push_styled(Style::builder().bg(Color::RGB { r: 1, g: 2, b: 3 }).link("https://x.y"), "link");
+        buffer.push_styled(Style::builder().italic(), "\nd");
+        assert_eq!(buffer.rendered_len(), buffer.to_string().len());
+
+        assert_eq!(TerminalBuffer::new().rendered_len(), 0);
+    }
+
+    #[test]
+    fn render_line_matches_its_part_of_the_buffer() {
+        let mut buffer = TerminalBuffer::new();
+        buffer.push_styled(Style::builder().fg(Color::RED), "ab");
+        buffer.push("c\n");
+        buffer.push_styled(Style::builder().bold(), "d");
+        buffer.push("x\ne");
+
+        let rendered = buffer.to_string();
+        for (l, line) in rendered.split('\n').enumerate() {
+            let own = buffer.render_line(l).unwrap();
+            // A styled line starts with its own reset
+            assert_eq!(own.strip_prefix("\x1b[0m").unwrap_or(&own), line);
+        }
+        assert_eq!(buffer.render_line(0).unwrap(), "\x1b[0m\x1b[31mab\x1b[39mc");
+        assert_eq!(buffer.render_line(2).unwrap(), "e");
+        assert_eq!(buffer.render_line(3), None);
+    }
+
+    #[test]
+    fn double_width_line_is_prefixed() {
+        let mut buffer = TerminalBuffer::new();
+        buffer.push("Title\nbody");
+        buffer.set_line_size(0, LineSize::DoubleWidth);
+        buffer.set_line_size(2, LineSize::DoubleHeightTop);
+        assert_eq!(buffer.to_string(), "\x1b#6Title\nbody\n\x1b#3");
+        assert_eq!(buffer.render_line(0).unwrap(), "\x1b#6Title");
+    }
+
+    #[test]
+    fn ruler_labels_line_up_with_the_columns() {
+        let mut buffer = TerminalBuffer::new();
+        buffer.push("abcdefghijkl\n日b");
+        let ruler = buffer.with_ruler();
+        assert_eq!(ruler, "            1\n  012345678901\n0|abcdefghijkl\n1|日b");
+
+        // The tens digit is over column 10, where `k` is
+        let lines = ruler.lines().collect::<Vec<_>>();
+        assert_eq!(lines[0].find('1'), lines[2].find('k'));
+    }
+
+    #[test]
+    fn rtl_line_renders_reversed() {
+        let mut buffer = TerminalBuffer::new();
+        buffer.set_direction(0, Direction::Rtl);
+        buffer.push("abc");
+        buffer.push_styled(Style::builder().bold(), "d");
+        buffer.push("\nef");
+        assert_eq!(buffer.to_string(), "\x1b[1md\x1b[22mcba\nef");
+        // Characters are still stored in logical order
+        assert_eq!(buffer.to_plain(), "abcd\nef");
+        assert_eq!(buffer.char_at(0, 0), Some('a'));
+    }
+
+    #[test]
+    fn wrap_markers_are_drawn_at_the_breaks() {
+        let wrapped = |marker: WrapMarker| {
+            let mut buffer = TerminalBuffer::new();
+            buffer.set_wrap_width(Some(4));
+            buffer.set_wrap_marker(Some(marker));
+            buffer.push("abcdefgh");
+            (buffer.to_string(), buffer.to_plain())
+        };
+
+        let (rendered, plain) = wrapped(WrapMarker::Continuation('>', Style::builder().fg(Color::BLUE)));
+        assert_eq!(rendered, "abcd\n\x1b[34m>\x1b[39mefg\n\x1b[34m>\x1b[39mh");
+        assert_eq!(plain, "abcdefgh");
+
+        let (rendered, plain) = wrapped(WrapMarker::Break('\\', Style::default()));
+        assert_eq!(rendered, "abc\\\ndef\\\ngh");
+        assert_eq!(plain, "abcdefgh");
+    }
+
+    #[test]
+    fn dithering_mixes_more_colors_than_the_nearest() {
+        let gradient = (0..32u8).map(|i| Color::RGB { r: i * 8, g: 96, b: 255 - i * 8 });
+        let (mut dithered, mut nearest) = (TerminalBuffer::new(), TerminalBuffer::new());
+        for color in gradient {
+            dithered.push_styled(Style::builder().bg(color), " ");
+            nearest.push_styled(Style::builder().bg(color.to_ansi16()), " ");
+        }
+
+        dithered.dither_region(.., .., ColorSupport::Standard);
+        // Every cell is downsampled to a 16 color background, and each distinct color is one style
+        assert!(!dithered.to_string().contains("48;"));
+        assert!(dithered.style_count() > nearest.style_count());
+    }
+
+    #[test]
+    fn set_past_the_end_fills_with_spaces() {
+        let red = Style::builder().fg(Color::RED);
+        let mut buffer = TerminalBuffer::new();
+        buffer.push("ab");
+
+        buffer.set(0, 5, 'x', red.clone());
+        assert_eq!(buffer.to_string(), "ab   \x1b[31mx\x1b[39m");
+        assert_eq!(refs(&buffer, &red), 1);
+
+        buffer.set(0, 5, 'z', Style::default());
+        assert_eq!(buffer.to_string(), "ab   z");
+        assert_eq!(refs(&buffer, &red), 0);
+
+        buffer.set(2, 1, 'y', Style::default());
+        assert_eq!(buffer.to_string(), "ab   z\n\n y");
+    }
+
+    #[test]
+    fn render_fmt_appends_the_display_output() {
+        let mut buffer = TerminalBuffer::new();
+        buffer.push_styled(Style::builder().bold(), "bold");
+        buffer.push(" plain\nnext");
+
+        let mut out = String::from("> ");
+        buffer.render_fmt(&mut out).unwrap();
+        assert_eq!(out, format!("> {}", buffer));
+    }
+
+    #[test]
+    fn style_and_char_at_a_cell() {
+        let red = Style::builder().fg(Color::RED);
+        let mut buffer = TerminalBuffer::new();
+        buffer.push("a");
+        buffer.push_styled(red.clone(), "日b");
+
+        assert_eq!(buffer.char_at(0, 0), Some('a'));
+        assert_eq!(buffer.style_at(0, 0), None);
+        // Both columns of a wide character map to it
+        assert_eq!(buffer.char_at(0, 1), Some('日'));
+        assert_eq!(buffer.char_at(0, 2), Some('日'));
+        assert_eq!(buffer.style_at(0, 2), Some(&red));
+        assert_eq!(buffer.char_at(0, 3), Some('b'));
+        assert_eq!(buffer.char_at(0, 4), None);
+        assert_eq!(buffer.style_at(1, 0), None);
+    }
+
+    #[test]
+    fn combining_marks_share_the_cell_before_them() {
+        let mut buffer = TerminalBuffer::new();
+        buffer.push("e\u{301}中");
+        buffer.push("\u{308}");
+        let line = &buffer.buffer[0];
+        assert_eq!(line.len(), 2);
+        assert_eq!((line[0].character(), line[0].combining(), line[0].width()), ('e', "\u{301}", 1));
+        assert_eq!((line[1].character(), line[1].combining(), line[1].width()), ('中', "\u{308}", 2));
+        assert_eq!(line_width(line), 3);
+        assert_eq!(buffer.to_string(), "e\u{301}中\u{308}");
+    }
+
+    #[test]
+    fn render_region_cuts_the_window() {
+        let mut buffer = TerminalBuffer::new();
+        buffer.push("a日b\ncd\nef");
+        // 日 takes up columns 1 and 2, half of it in the region is a space
+        assert_eq!(buffer.render_region(0..2, 2..4), " b\n");
+        assert_eq!(buffer.render_region(1.., ..1), "c\ne");
+        assert_eq!(buffer.render_region(2..9, 1..9), "f");
+    }
+
+    #[test]
+    fn tab_expands_to_the_next_stop() {
+        let mut buffer = TerminalBuffer::new();
+        buffer.set_tab_width(4);
+        buffer.push("a\tb");
+        assert_eq!(buffer.to_string(), "a   b");
+        assert_eq!(buffer.logical_columns(0), vec![0, 1, 4]);
+        assert_eq!(buffer.next_column(0, 1), 4);
+        assert_eq!(buffer.prev_column(0, 4), 1);
+        assert_eq!(buffer.next_column(0, 4), 5);
     }
-    println!("{}", buffer);
 }
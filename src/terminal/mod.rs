@@ -1,9 +1,9 @@
-use std::io::{Read, Write};
-
 use lazy_static::lazy_static;
 
+use crate::style::Color;
+
 pub mod buffer;
-mod command;
+pub mod command;
 
 #[derive(Default, Debug, Copy, Clone, PartialEq, Eq)]
 pub enum ColorSupport {
@@ -16,13 +16,19 @@ pub enum ColorSupport {
 
 impl ColorSupport {
     pub fn new() -> Self {
+        ColorSupport::from_env(|key| std::env::var(key).ok())
+    }
+
+    /// Detect the color support from `COLORTERM` and `TERM`, given a lookup of environment
+    /// variables.
+    fn from_env<F: Fn(&str) -> Option<String>>(env: F) -> Self {
         let color_term_type = {
-            let term_type = std::env::var("TERM")
+            let term_type = env("TERM")
                 .map_or(
                     "dumb".to_string(),
                     |v| v.strip_prefix("xterm-").map_or("dump".to_string(), |v| v.to_string()),
                 );
-            std::env::var("COLORTERM")
+            env("COLORTERM")
                 .unwrap_or(term_type)
         };
         match color_term_type.as_str() {
@@ -31,6 +37,29 @@ impl ColorSupport {
             _ => ColorSupport::default()
         }
     }
+
+    /// Whether the color can be rendered faithfully with this level of color support.
+    pub fn supports(&self, color: &Color) -> bool {
+        match color {
+            Color::Ansi(value) => match self {
+                ColorSupport::None => false,
+                ColorSupport::Standard => *value < 16,
+                ColorSupport::EightBit | ColorSupport::TrueColor => true,
+            },
+            // Renders as the default color
+            Color::RGBA { a: 0, .. } => *self != ColorSupport::None,
+            Color::RGB { .. }
+            | Color::RGBA { .. }
+            | Color::HSL { .. }
+            | Color::HSV { .. }
+            | Color::HWB { .. }
+            | Color::Oklch { .. }
+            | Color::CYMK { .. } => {
+                *self == ColorSupport::TrueColor
+            }
+            _ => *self != ColorSupport::None,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -39,16 +68,197 @@ pub struct Capabilities {
     pub color: ColorSupport,
 }
 
+impl Capabilities {
+    /// Whether the color can be rendered faithfully by the terminal.
+    pub fn supports(&self, color: &Color) -> bool {
+        self.ansi && self.color.supports(color)
+    }
+}
+
 lazy_static! {
     pub static ref CAPABILITIES: Capabilities = Capabilities::default();
 }
 
 impl Default for Capabilities {
+    /// Detect the capabilities of the terminal from the environment.
+    ///
+    /// `CLICOLOR_FORCE` forces ansi and color on, then `NO_COLOR` turns them off, then the
+    /// terminal is detected. Either variable only counts when it is set and not empty, and
+    /// `CLICOLOR_FORCE=0` doesn't force anything.
     fn default() -> Self {
-        let term_type = std::env::var("TERM").unwrap_or("dumb".to_string());
+        let env = |key: &str| std::env::var(key).ok();
+        Capabilities::from_overrides(env, || Capabilities::detect(env))
+    }
+}
+
+impl Capabilities {
+    /// Apply the `CLICOLOR_FORCE` and `NO_COLOR` overrides, given a lookup of environment
+    /// variables, before falling back to detection.
+    fn from_overrides<F: Fn(&str) -> Option<String>, D: FnOnce() -> Capabilities>(env: F, detect: D) -> Self {
+        let set = |key: &str| env(key).filter(|value| !value.is_empty());
+        if set("CLICOLOR_FORCE").is_some_and(|value| value != "0") {
+            let color = match detect().color {
+                ColorSupport::None => ColorSupport::Standard,
+                color => color,
+            };
+            return Capabilities { ansi: true, color };
+        }
+        if set("NO_COLOR").is_some() {
+            return Capabilities { ansi: false, color: ColorSupport::None };
+        }
+        detect()
+    }
+
+    /// Detect the terminal, given a lookup of environment variables.
+    ///
+    /// On Windows, virtual terminal processing is enabled on the console and `ansi` is whether
+    /// it could be enabled. Windows Terminal supports truecolor, but the older ConHost may not
+    /// so it falls back to the usual detection.
+    #[cfg(windows)]
+    fn detect<F: Fn(&str) -> Option<String>>(env: F) -> Self {
+        let color = if windows::is_windows_terminal(&env) {
+            ColorSupport::TrueColor
+        } else {
+            ColorSupport::from_env(env)
+        };
+        Capabilities {
+            ansi: windows::enable_virtual_terminal(),
+            color,
+        }
+    }
+
+    #[cfg(not(windows))]
+    fn detect<F: Fn(&str) -> Option<String>>(env: F) -> Self {
+        let term_type = env("TERM").unwrap_or("dumb".to_string());
         Capabilities {
             ansi: term_type.as_str() != "dumb",
-            color: ColorSupport::new(),
+            color: ColorSupport::from_env(env),
+        }
+    }
+}
+
+#[cfg(windows)]
+mod windows {
+    use std::ffi::c_void;
+
+    type Handle = *mut c_void;
+
+    const STD_OUTPUT_HANDLE: u32 = -11i32 as u32;
+    const INVALID_HANDLE_VALUE: Handle = -1isize as Handle;
+    const ENABLE_VIRTUAL_TERMINAL_PROCESSING: u32 = 0x0004;
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn GetStdHandle(std_handle: u32) -> Handle;
+        fn GetConsoleMode(console: Handle, mode: *mut u32) -> i32;
+        fn SetConsoleMode(console: Handle, mode: u32) -> i32;
+    }
+
+    /// Enable virtual terminal processing on stdout, returning whether ansi sequences will be
+    /// processed. Fails when stdout isn't a console or the console is too old.
+    pub fn enable_virtual_terminal() -> bool {
+        // SAFETY: The handle is checked before use and the mode is a valid pointer to a u32
+        unsafe {
+            let handle = GetStdHandle(STD_OUTPUT_HANDLE);
+            if handle.is_null() || handle == INVALID_HANDLE_VALUE {
+                return false;
+            }
+            let mut mode = 0;
+            if GetConsoleMode(handle, &mut mode) == 0 {
+                return false;
+            }
+            mode & ENABLE_VIRTUAL_TERMINAL_PROCESSING != 0
+                || SetConsoleMode(handle, mode | ENABLE_VIRTUAL_TERMINAL_PROCESSING) != 0
         }
     }
-}
\ No newline at end of file
+
+    /// Whether the process is running in Windows Terminal, given a lookup of environment variables.
+    pub fn is_windows_terminal<F: Fn(&str) -> Option<String>>(env: &F) -> bool {
+        env("WT_SESSION").is_some() || env("WT_PROFILE_ID").is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Capabilities from the overrides of the given environment, detecting a 256 color terminal.
+    fn overrides(vars: &[(&str, &str)]) -> Capabilities {
+        let env = |key: &str| vars.iter().find(|(k, _)| *k == key).map(|(_, value)| value.to_string());
+        Capabilities::from_overrides(env, || Capabilities { ansi: true, color: ColorSupport::EightBit })
+    }
+
+    #[test]
+    fn force_beats_no_color() {
+        let caps = overrides(&[("CLICOLOR_FORCE", "1"), ("NO_COLOR", "1")]);
+        assert_eq!(caps, Capabilities { ansi: true, color: ColorSupport::EightBit });
+        let caps = overrides(&[("NO_COLOR", "1")]);
+        assert_eq!(caps, Capabilities { ansi: false, color: ColorSupport::None });
+    }
+
+    #[test]
+    fn empty_no_color_is_ignored() {
+        assert_eq!(overrides(&[("NO_COLOR", "")]), Capabilities { ansi: true, color: ColorSupport::EightBit });
+    }
+
+    #[test]
+    fn zero_clicolor_force_forces_nothing() {
+        let caps = overrides(&[("CLICOLOR_FORCE", "0"), ("NO_COLOR", "1")]);
+        assert_eq!(caps, Capabilities { ansi: false, color: ColorSupport::None });
+        let caps = Capabilities::from_overrides(
+            |key| (key == "CLICOLOR_FORCE").then(|| "0".to_string()),
+            || Capabilities { ansi: false, color: ColorSupport::None },
+        );
+        assert_eq!(caps, Capabilities { ansi: false, color: ColorSupport::None });
+    }
+
+    /// A lookup of the given environment variables.
+    fn env(vars: &'static [(&'static str, &'static str)]) -> impl Fn(&str) -> Option<String> {
+        move |key| vars.iter().find(|(k, _)| *k == key).map(|(_, value)| value.to_string())
+    }
+
+    #[test]
+    fn color_support_from_env() {
+        assert_eq!(ColorSupport::from_env(env(&[("COLORTERM", "truecolor")])), ColorSupport::TrueColor);
+        assert_eq!(ColorSupport::from_env(env(&[("TERM", "xterm-256color")])), ColorSupport::EightBit);
+        assert_eq!(ColorSupport::from_env(env(&[("TERM", "xterm")])), ColorSupport::Standard);
+        assert_eq!(ColorSupport::from_env(env(&[])), ColorSupport::Standard);
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn windows_terminal_is_truecolor() {
+        let detect = |vars| Capabilities::detect(env(vars)).color;
+        assert_eq!(detect(&[("WT_SESSION", "1"), ("TERM", "xterm")]), ColorSupport::TrueColor);
+        assert_eq!(detect(&[("WT_PROFILE_ID", "1")]), ColorSupport::TrueColor);
+        // ConHost falls back to the regular detection
+        assert_eq!(detect(&[("TERM", "xterm-256color")]), ColorSupport::EightBit);
+        assert_eq!(detect(&[]), ColorSupport::Standard);
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn windows_terminal_from_env() {
+        assert!(windows::is_windows_terminal(&env(&[("WT_SESSION", "1")])));
+        assert!(windows::is_windows_terminal(&env(&[("WT_PROFILE_ID", "1")])));
+        assert!(!windows::is_windows_terminal(&env(&[("TERM", "xterm")])));
+    }
+
+    #[test]
+    fn supports_by_tier() {
+        use ColorSupport::*;
+
+        let rgb = Color::RGB { r: 1, g: 2, b: 3 };
+        let supported = |color: &Color| [None, Standard, EightBit, TrueColor].map(|tier| tier.supports(color));
+        assert_eq!(supported(&rgb), [false, false, false, true]);
+        assert_eq!(supported(&Color::Ansi(200)), [false, false, true, true]);
+        assert_eq!(supported(&Color::Ansi(9)), [false, true, true, true]);
+        assert_eq!(supported(&Color::RED), [false, true, true, true]);
+
+        let caps = Capabilities { ansi: true, color: EightBit };
+        assert!(caps.supports(&Color::Ansi(200)) && caps.supports(&Color::RED) && !caps.supports(&rgb));
+        // Without ansi nothing can be rendered
+        let caps = Capabilities { ansi: false, color: TrueColor };
+        assert!(!caps.supports(&rgb) && !caps.supports(&Color::Ansi(200)) && !caps.supports(&Color::RED));
+    }
+}